@@ -7,34 +7,283 @@ use failure::{Error, format_err, ensure};
 
 pub fn aoc10(part2: bool) -> Result<(), Error> {
     let mut particle_field = parse_particles(&mut io::stdin().lock())?;
-    particle_field.advance_to_best_distance(0);
+    particle_field.advance_to_convergence()?;
     if part2 {
         println!("{}", particle_field.cur_step);
     } else {
         println!("{}", particle_field);
+        let message = read_message(&particle_field.bitmap());
+        println!("Message: {}", message.text);
+        for glyph in &message.unknown_glyphs {
+            eprintln!("Unrecognized glyph:\n{}", glyph);
+        }
+    }
+    Ok(())
+}
+
+/// Prints every time a particle passes through `region`, given as
+/// `min_x,min_y,max_x,max_y`, between the steps in `steps`, given as
+/// `first..last` (inclusive).
+pub fn aoc10_region(region: &str, steps: &str) -> Result<(), Error> {
+    let particle_field = parse_particles(&mut io::stdin().lock())?;
+    let region = parse_region(region)?;
+    let (first, last) = steps.split_once("..").ok_or_else(|| format_err!("Can't parse step range {}", steps))?;
+    for visit in particle_field.visits(&region, first.trim().parse()?, last.trim().parse()?) {
+        println!("{}", visit);
     }
     Ok(())
 }
 
+/// Parses a region like `-3,-3,3,3`, given as its corners.
+fn parse_region(region: &str) -> Result<BoundingBox, Error> {
+    let coords = region.split(',').map(|c| c.trim().parse::<i64>()).collect::<Result<Vec<_>, _>>()?;
+    ensure!(coords.len() == 4, "Region {} should be min_x,min_y,max_x,max_y", region);
+    Ok(BoundingBox {
+        min_x: coords[0].min(coords[2]),
+        max_x: coords[0].max(coords[2]),
+        min_y: coords[1].min(coords[3]),
+        max_y: coords[1].max(coords[3]),
+    })
+}
+
+/// A block font, with each glyph drawn as rows of `#` and `.`.
+struct Font {
+    height: usize,
+    glyphs: &'static [(char, &'static [&'static str])],
+}
+
+/// The 6-row font used by most puzzles that draw letters.
+const SMALL_FONT: Font = Font {
+    height: 6,
+    glyphs: &[
+        ('A', &[".##.", "#..#", "#..#", "####", "#..#", "#..#"]),
+        ('B', &["###.", "#..#", "###.", "#..#", "#..#", "###."]),
+        ('C', &[".##.", "#..#", "#...", "#...", "#..#", ".##."]),
+        ('E', &["####", "#...", "###.", "#...", "#...", "####"]),
+        ('F', &["####", "#...", "###.", "#...", "#...", "#..."]),
+        ('G', &[".##.", "#..#", "#...", "#.##", "#..#", ".###"]),
+        ('H', &["#..#", "#..#", "####", "#..#", "#..#", "#..#"]),
+        ('I', &["###", ".#.", ".#.", ".#.", ".#.", "###"]),
+        ('J', &["..##", "...#", "...#", "...#", "#..#", ".##."]),
+        ('K', &["#..#", "#.#.", "##..", "#.#.", "#.#.", "#..#"]),
+        ('L', &["#...", "#...", "#...", "#...", "#...", "####"]),
+        ('O', &[".##.", "#..#", "#..#", "#..#", "#..#", ".##."]),
+        ('P', &["###.", "#..#", "#..#", "###.", "#...", "#..."]),
+        ('R', &["###.", "#..#", "#..#", "###.", "#.#.", "#..#"]),
+        ('S', &[".###", "#...", "#...", ".##.", "...#", "###."]),
+        ('U', &["#..#", "#..#", "#..#", "#..#", "#..#", ".##."]),
+        ('Y', &["#...#", "#...#", ".#.#.", "..#..", "..#..", "..#.."]),
+        ('Z', &["####", "...#", "..#.", ".#..", "#...", "####"]),
+    ],
+};
+
+/// The 10-row font this puzzle's messages are written in.
+const LARGE_FONT: Font = Font {
+    height: 10,
+    glyphs: &[
+        ('A', &["..##..", ".#..#.", "#....#", "#....#", "#....#", "######", "#....#", "#....#", "#....#", "#....#"]),
+        ('B', &["#####.", "#....#", "#....#", "#....#", "#####.", "#....#", "#....#", "#....#", "#....#", "#####."]),
+        ('C', &[".####.", "#....#", "#.....", "#.....", "#.....", "#.....", "#.....", "#.....", "#....#", ".####."]),
+        ('E', &["######", "#.....", "#.....", "#.....", "#####.", "#.....", "#.....", "#.....", "#.....", "######"]),
+        ('F', &["######", "#.....", "#.....", "#.....", "#####.", "#.....", "#.....", "#.....", "#.....", "#....."]),
+        ('G', &[".####.", "#....#", "#.....", "#.....", "#.....", "#..###", "#....#", "#....#", "#...##", ".###.#"]),
+        ('H', &["#....#", "#....#", "#....#", "#....#", "######", "#....#", "#....#", "#....#", "#....#", "#....#"]),
+        ('J', &["...###", "....#.", "....#.", "....#.", "....#.", "....#.", "....#.", "#...#.", "#...#.", ".###.."]),
+        ('K', &["#....#", "#...#.", "#..#..", "#.#...", "##....", "##....", "#.#...", "#..#..", "#...#.", "#....#"]),
+        ('L', &["#.....", "#.....", "#.....", "#.....", "#.....", "#.....", "#.....", "#.....", "#.....", "######"]),
+        ('N', &["#....#", "##...#", "##...#", "#.#..#", "#.#..#", "#..#.#", "#..#.#", "#...##", "#...##", "#....#"]),
+        ('P', &["#####.", "#....#", "#....#", "#....#", "#####.", "#.....", "#.....", "#.....", "#.....", "#....."]),
+        ('R', &["#####.", "#....#", "#....#", "#....#", "#####.", "#..#..", "#...#.", "#...#.", "#....#", "#....#"]),
+        ('X', &["#....#", "#....#", ".#..#.", ".#..#.", "..##..", "..##..", ".#..#.", ".#..#.", "#....#", "#....#"]),
+        ('Z', &["######", ".....#", ".....#", "....#.", "...#..", "..#...", ".#....", "#.....", "#.....", "######"]),
+    ],
+};
+
+/// Text read from a bitmap.
+#[derive(Debug, PartialEq)]
+pub struct Message {
+    /// The recognized letters, with `?` for each unrecognized glyph.
+    pub text: String,
+    /// Each unrecognized glyph drawn in `#` and `.`, in order.
+    pub unknown_glyphs: Vec<String>,
+}
+
+fn draw(rows: &[Vec<bool>]) -> String {
+    rows.iter().map(|row| row.iter().map(|&b| if b { '#' } else { '.' }).collect::<String>() + "\n").collect()
+}
+
+/// Reads letters written in one of the block fonts. Glyphs are
+/// separated by empty columns; glyphs that don't match a letter in the
+/// font for the bitmap's height, or any glyph if there's no font that
+/// tall, come out as `?`.
+pub fn read_message(bitmap: &[Vec<bool>]) -> Message {
+    let fonts = [SMALL_FONT, LARGE_FONT];
+    let font = fonts.iter().find(|f| f.height == bitmap.len());
+    let width = bitmap.iter().map(|row| row.len()).max().unwrap_or(0);
+    let column_used = |x: usize| bitmap.iter().any(|row| row.get(x) == Some(&true));
+    let mut message = Message { text: String::new(), unknown_glyphs: vec![] };
+    let mut x = 0;
+    while x < width {
+        if !column_used(x) {
+            x += 1;
+            continue;
+        }
+        let start = x;
+        while x < width && column_used(x) {
+            x += 1;
+        }
+        let glyph: Vec<Vec<bool>> = bitmap.iter()
+            .map(|row| (start..x).map(|i| row.get(i) == Some(&true)).collect())
+            .collect();
+        let drawn = draw(&glyph);
+        let letter = font.and_then(|font| font.glyphs.iter().find(|(_, rows)| {
+            // Compare ignoring any padding columns in the font.
+            let rows: Vec<Vec<bool>> = rows.iter().map(|r| r.chars().map(|c| c == '#').collect()).collect();
+            let used: Vec<usize> = (0..rows[0].len()).filter(|&i| rows.iter().any(|r| r[i])).collect();
+            let trimmed: Vec<Vec<bool>> = rows.iter().map(|r| used.iter().map(|&i| r[i]).collect()).collect();
+            trimmed == glyph
+        }));
+        match letter {
+            Some((letter, _)) => message.text.push(*letter),
+            None => {
+                message.text.push('?');
+                message.unknown_glyphs.push(drawn);
+            },
+        }
+    }
+    message
+}
+
+/// Parses lines like `position=< 9,  1> velocity=< 0,  2>`, optionally
+/// followed by ` acceleration=<-1,  0>`.
 fn parse_particles(input: &mut impl BufRead) -> Result<ParticleField, Error> {
-    let regex = Regex::new(r"position=<([- 0-9]+), ([- 0-9]+)> velocity=<([- 0-9]+), ([- 0-9]+)>")?;
+    let regex = Regex::new(r"position=<([- 0-9]+), ([- 0-9]+)> velocity=<([- 0-9]+), ([- 0-9]+)>(?: acceleration=<([- 0-9]+), ([- 0-9]+)>)?")?;
     let mut particles = vec![];
     for line_res in input.lines() {
         let line = line_res?;
         let caps = regex.captures(&line).ok_or_else(|| format_err!("Can't parse line {}", line))?;
         let coords: Vec<_> = caps.iter().skip(1).flat_map(|c_opt| c_opt.and_then(|c| c.as_str().trim().parse::<i64>().ok())).collect();
-        ensure!(coords.len() == 4, "Line {} has fewer than 4 coordinates", line);
-        particles.push(Particle { x: coords[0], y: coords[1], vel_x: coords[2], vel_y: coords[3] });
+        ensure!(coords.len() == 4 || coords.len() == 6, "Line {} has the wrong number of coordinates", line);
+        let (acc_x, acc_y) = if coords.len() == 6 { (coords[4], coords[5]) } else { (0, 0) };
+        particles.push(Particle { x: coords[0], y: coords[1], vel_x: coords[2], vel_y: coords[3], acc_x, acc_y });
     }
     Ok(ParticleField { particles, cur_step: 0 })
 }
 
+/// A particle's position and velocity, and its constant acceleration.
+///
+/// Motion is in discrete steps: each step the acceleration is added to
+/// the velocity, and then the new velocity to the position. After `t`
+/// steps a coordinate is at `x + v t + a t (t + 1) / 2`, which holds for
+/// negative `t` too, so stepping backwards undoes stepping forwards
+/// exactly.
 #[derive(PartialEq, Debug)]
 struct Particle {
     x: i64,
     y: i64,
     vel_x: i64,
     vel_y: i64,
+    acc_x: i64,
+    acc_y: i64,
+}
+
+/// How far a coordinate moves in `steps` steps from velocity `vel` with
+/// constant acceleration `acc`.
+fn displacement(vel: i64, acc: i64, steps: i64) -> i64 {
+    vel * steps + acc * (steps * (steps + 1) / 2)
+}
+
+impl Particle {
+    /// The steps from now, within `window`, at which this particle is
+    /// inside `region`, as sorted, disjoint, inclusive ranges.
+    fn steps_within(&self, region: &BoundingBox, window: (i64, i64)) -> Vec<(i64, i64)> {
+        let xs = steps_between(self.x, self.vel_x, self.acc_x, (region.min_x, region.max_x), window);
+        let ys = steps_between(self.y, self.vel_y, self.acc_y, (region.min_y, region.max_y), window);
+        let mut steps = vec![];
+        for &(x_start, x_end) in &xs {
+            for &(y_start, y_end) in &ys {
+                let (start, end) = (x_start.max(y_start), x_end.min(y_end));
+                if start <= end {
+                    steps.push((start, end));
+                }
+            }
+        }
+        steps.sort_unstable();
+        steps
+    }
+}
+
+/// The steps within `window` at which a coordinate starting at `pos`
+/// lies within `bounds`, as at most two sorted, inclusive ranges.
+///
+/// Twice the coordinate is the integer quadratic `a t^2 + (2 v + a) t +
+/// 2 x`, which is monotonic on either side of its vertex, so each side
+/// is binary searched for where it enters and leaves the bounds.
+fn steps_between(pos: i64, vel: i64, acc: i64, bounds: (i64, i64), window: (i64, i64)) -> Vec<(i64, i64)> {
+    let (a, b, c) = (i128::from(acc), 2 * i128::from(vel) + i128::from(acc), 2 * i128::from(pos));
+    let doubled = |t: i64| a * i128::from(t) * i128::from(t) + b * i128::from(t) + c;
+    let (low, high) = (2 * i128::from(bounds.0), 2 * i128::from(bounds.1));
+    let (first, last) = window;
+    let mut pieces = vec![];
+    if a == 0 || first > last {
+        pieces.push((first, last));
+    } else {
+        // The last step at or before the vertex at -b / 2a, rounding
+        // down whatever the sign of a.
+        let (num, den) = if a > 0 { (-b, 2 * a) } else { (b, -2 * a) };
+        let vertex = num.div_euclid(den).clamp(i128::from(first) - 1, i128::from(last)) as i64;
+        pieces.push((first, vertex));
+        pieces.push((vertex + 1, last));
+    }
+    let mut ranges = vec![];
+    for (start, end) in pieces {
+        if start > end {
+            continue;
+        }
+        let rising = doubled(end) >= doubled(start);
+        let (enter, leave) = if rising {
+            (first_step(start, end, |t| doubled(t) >= low), first_step(start, end, |t| doubled(t) > high))
+        } else {
+            (first_step(start, end, |t| doubled(t) <= high), first_step(start, end, |t| doubled(t) < low))
+        };
+        if enter < leave {
+            ranges.push((enter, leave - 1));
+        }
+    }
+    ranges
+}
+
+/// The first step in `start..=end` for which `pred` holds, or `end + 1`
+/// if there isn't one. `pred` must be false and then true.
+fn first_step(start: i64, end: i64, pred: impl Fn(i64) -> bool) -> i64 {
+    let (mut lo, mut hi) = (i128::from(start), i128::from(end) + 1);
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        if pred(mid as i64) {
+            hi = mid;
+        } else {
+            lo = mid + 1;
+        }
+    }
+    lo as i64
+}
+
+/// A stretch of steps during which a particle is inside a region.
+#[derive(PartialEq, Debug)]
+pub struct RegionVisit {
+    pub particle: usize,
+    pub first_step: i64,
+    pub last_step: i64,
+}
+
+impl Display for RegionVisit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.first_step == self.last_step {
+            write!(f, "Particle {} is in the region at step {}", self.particle, self.first_step)
+        } else {
+            write!(f, "Particle {} is in the region from step {} to step {}", self.particle, self.first_step, self.last_step)
+        }
+    }
 }
 
 #[derive(PartialEq, Debug)]
@@ -48,8 +297,10 @@ impl ParticleField {
     /// timesteps. Negative values are allowed.
     fn advance(&mut self, steps: i64) {
         for particle in self.particles.iter_mut() {
-            particle.x += particle.vel_x * steps;
-            particle.y += particle.vel_y * steps;
+            particle.x += displacement(particle.vel_x, particle.acc_x, steps);
+            particle.y += displacement(particle.vel_y, particle.acc_y, steps);
+            particle.vel_x += particle.acc_x * steps;
+            particle.vel_y += particle.acc_y * steps;
         }
         self.cur_step += steps;
     }
@@ -58,95 +309,238 @@ impl ParticleField {
         self.advance(step - self.cur_step);
     }
 
-    /// Get the sum of Manhattan distances between every
-    /// particle. Every pair is counted twice.
-    fn mutual_distance(&self) -> u64 {
-        let mut distance: u64 = 0;
-        for particle1 in self.particles.iter() {
-            for particle2 in self.particles.iter() {
-                distance += ((particle1.x - particle2.x).abs() + (particle1.y - particle2.x).abs()) as u64;
-            }
+    /// Every time a particle is inside `region` (inclusive) between
+    /// steps `first` and `last`, ordered by when they enter it.
+    pub fn visits(&self, region: &BoundingBox, first: i64, last: i64) -> Vec<RegionVisit> {
+        let window = (first - self.cur_step, last - self.cur_step);
+        let mut visits: Vec<RegionVisit> = self.particles.iter().enumerate()
+            .flat_map(|(i, particle)| particle.steps_within(region, window).into_iter().map(move |(start, end)| RegionVisit {
+                particle: i,
+                first_step: self.cur_step + start,
+                last_step: self.cur_step + end,
+            }))
+            .collect();
+        visits.sort_by_key(|visit| (visit.first_step, visit.particle));
+        visits
+    }
+
+    /// The smallest box containing every particle.
+    pub fn bounding_box(&self) -> BoundingBox {
+        BoundingBox {
+            min_x: self.particles.iter().map(|p| p.x).min().unwrap(),
+            max_x: self.particles.iter().map(|p| p.x).max().unwrap(),
+            min_y: self.particles.iter().map(|p| p.y).min().unwrap(),
+            max_y: self.particles.iter().map(|p| p.y).max().unwrap(),
         }
-        distance
-    }
-
-    fn deriv_at(&mut self, step: i64) -> i64 {
-        let old_step = self.cur_step;
-        self.advance_to_step(step);
-        let dist = self.mutual_distance() as i64;
-        self.advance(1);
-        let next_dist = self.mutual_distance() as i64;
-        self.advance_to_step(old_step);
-        next_dist - dist
-    }
-
-    /// Find the step with lowest mutual distance in O(p^2 log n) time
-    /// (p: number of particles, n: value of the best step).
-    fn advance_to_best_distance(&mut self, fudge_steps: i64) -> i64 {
-        let mut best_distance = self.mutual_distance();
-        let mut cur_increase = 1;
-        let mut lo = 0;
-        let mut hi;
-        // Step 1: advance by exponentially growing amounts until we
-        // find the region that must contain the best step. We will
-        // find this region in O(log n) loops.
-        loop {
-            self.advance(cur_increase);
-            let distance = self.mutual_distance();
-            if distance > best_distance {
-                // We've overshot.
-                hi = self.cur_step;
-                break;
-            } else {
-                lo = self.cur_step - cur_increase;
-                best_distance = distance;
+    }
+
+    /// Finds the step, from now on, at which the particles' bounding
+    /// box has the smallest area, and the box at that step. The
+    /// earliest step wins ties.
+    ///
+    /// Each side of the box moves along the envelope of the particles'
+    /// straight-line paths, so between the envelopes' breakpoints the
+    /// area is a quadratic in time. We minimize each of those pieces
+    /// exactly, in O(p log p) time overall. The area itself isn't
+    /// unimodal, so a plain ternary search could get stuck.
+    ///
+    /// If every particle has the same acceleration, it shifts them all
+    /// together and doesn't change the box's size, so the same approach
+    /// works. Otherwise the sides are no longer straight lines and this
+    /// fails.
+    pub fn find_convergence(&self) -> Result<Convergence, Error> {
+        ensure!(!self.particles.is_empty(), "There are no particles to converge");
+        let (acc_x, acc_y) = self.particles.first().map_or((0, 0), |p| (p.acc_x, p.acc_y));
+        ensure!(self.particles.iter().all(|p| (p.acc_x, p.acc_y) == (acc_x, acc_y)),
+                "Can't find where particles converge when they accelerate differently");
+        let xs: Vec<Line> = self.particles.iter().map(|p| Line { start: p.x, slope: p.vel_x }).collect();
+        let ys: Vec<Line> = self.particles.iter().map(|p| Line { start: p.y, slope: p.vel_y }).collect();
+        let sides = [Envelope::upper(&xs), Envelope::lower(&xs), Envelope::upper(&ys), Envelope::lower(&ys)];
+
+        // Every integer step next to a breakpoint, so that all four
+        // sides are straight lines between consecutive candidates that
+        // aren't adjacent.
+        let mut candidates: Vec<i64> = sides.iter()
+            .flat_map(|side| side.breakpoints.iter())
+            .flat_map(|&(num, den)| vec![num.div_euclid(den), (num + den - 1).div_euclid(den)])
+            .filter(|&t| t > 0)
+            .map(|t| t.min(i128::from(i64::MAX)) as i64)
+            .collect();
+        candidates.push(0);
+        candidates.sort_unstable();
+        candidates.dedup();
+
+        let area_at = |t: i64, lines: &[Line; 4]| -> i128 {
+            let width = lines[0].at(t) - lines[1].at(t) + 1;
+            let height = lines[2].at(t) - lines[3].at(t) + 1;
+            width * height
+        };
+        let lines_at = |t: i64| [sides[0].line_at(t), sides[1].line_at(t), sides[2].line_at(t), sides[3].line_at(t)];
+        let mut best = (area_at(0, &lines_at(0)), 0);
+        for pair in candidates.windows(2) {
+            let (lo, hi) = (pair[0], pair[1]);
+            let lines = lines_at(hi);
+            let mut times = vec![hi];
+            // area = (w0 + w1 t)(h0 + h1 t), which opens upwards when
+            // both sides are growing or both shrinking. If the steps are
+            // adjacent, a breakpoint may lie between them, but then
+            // there's nothing between them to check anyway.
+            let width_slope = i128::from(lines[0].slope) - i128::from(lines[1].slope);
+            let height_slope = i128::from(lines[2].slope) - i128::from(lines[3].slope);
+            if hi - lo >= 2 && width_slope * height_slope > 0 {
+                let width_start = i128::from(lines[0].start) - i128::from(lines[1].start) + 1;
+                let height_start = i128::from(lines[2].start) - i128::from(lines[3].start) + 1;
+                // The vertex is at -(w0 h1 + h0 w1) / (2 w1 h1).
+                let num = -(width_start * height_slope + height_start * width_slope);
+                let den = 2 * width_slope * height_slope;
+                let vertex = num.div_euclid(den);
+                for t in [vertex, vertex + 1] {
+                    times.push(t.clamp(i128::from(lo), i128::from(hi)) as i64);
+                }
+            }
+            for t in times {
+                best = std::cmp::min(best, (area_at(t, &lines), t));
             }
-            cur_increase *= 2;
         }
+        // Past the last breakpoint every side is moving outwards, so the
+        // area only grows.
+
+        let (_, step) = best;
+        let lines = lines_at(step);
+        let (shift_x, shift_y) = (displacement(0, acc_x, step), displacement(0, acc_y, step));
+        Ok(Convergence {
+            step: self.cur_step + step,
+            bounding_box: BoundingBox {
+                max_x: lines[0].at(step) as i64 + shift_x,
+                min_x: lines[1].at(step) as i64 + shift_x,
+                max_y: lines[2].at(step) as i64 + shift_y,
+                min_y: lines[3].at(step) as i64 + shift_y,
+            },
+        })
+    }
+
+    /// Moves the particles to where they're closest together.
+    pub fn advance_to_convergence(&mut self) -> Result<Convergence, Error> {
+        let convergence = self.find_convergence()?;
+        self.advance_to_step(convergence.step);
+        Ok(convergence)
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct BoundingBox {
+    pub min_x: i64,
+    pub max_x: i64,
+    pub min_y: i64,
+    pub max_y: i64,
+}
+
+impl BoundingBox {
+    /// The number of cells in the box.
+    pub fn area(&self) -> u128 {
+        (self.max_x - self.min_x + 1) as u128 * (self.max_y - self.min_y + 1) as u128
+    }
+}
+
+/// Where the particles come closest together.
+#[derive(PartialEq, Debug)]
+pub struct Convergence {
+    pub step: i64,
+    pub bounding_box: BoundingBox,
+}
+
+/// A coordinate moving at constant speed.
+#[derive(PartialEq, Debug, Clone, Copy)]
+struct Line {
+    start: i64,
+    slope: i64,
+}
 
-        // Step 2: binary search to find the best step. Since the
-        // distance isn't monotone with respect to the step, normal
-        // binary search won't work. But since it must be convex, we
-        // instead binary search on the *derivative* of the distance
-        // to find the step with derivative closest to 0.
-        loop {
-            if lo >= hi {
-                break;
+impl Line {
+    fn at(&self, t: i64) -> i128 {
+        i128::from(self.start) + i128::from(self.slope) * i128::from(t)
+    }
+}
+
+/// The maximum (or minimum) of a set of lines over time, as the lines
+/// that are on top in order, and the times at which each hands over to
+/// the next.
+struct Envelope {
+    lines: Vec<Line>,
+    /// Breakpoint `i`, where `lines[i]` hands over to `lines[i + 1]`,
+    /// as a fraction with a positive denominator.
+    breakpoints: Vec<(i128, i128)>,
+    /// Whether this is a lower envelope stored upside down.
+    negated: bool,
+}
+
+impl Envelope {
+    fn upper(lines: &[Line]) -> Self {
+        let mut sorted = lines.to_vec();
+        sorted.sort_by_key(|l| (l.slope, l.start));
+        let mut hull: Vec<Line> = vec![];
+        for line in sorted {
+            // Equal slopes: the later one is at least as high.
+            if hull.last().is_some_and(|last| last.slope == line.slope) {
+                hull.pop();
             }
-            let mid = lo + (hi - lo) / 2;
-            if self.deriv_at(mid) > 0 {
-                hi = mid - 1;
-            } else {
-                lo = mid + 1;
+            // Drop the top line while the new one takes over from the
+            // line below it no later than the top line did.
+            while hull.len() >= 2 {
+                let (a, b) = (hull[hull.len() - 2], hull[hull.len() - 1]);
+                // crossing(a, line) <= crossing(a, b)
+                let lhs = (i128::from(a.start) - i128::from(line.start)) * (i128::from(b.slope) - i128::from(a.slope));
+                let rhs = (i128::from(a.start) - i128::from(b.start)) * (i128::from(line.slope) - i128::from(a.slope));
+                if lhs <= rhs {
+                    hull.pop();
+                } else {
+                    break;
+                }
             }
+            hull.push(line);
         }
+        let breakpoints = hull.windows(2)
+            .map(|pair| (i128::from(pair[0].start) - i128::from(pair[1].start), i128::from(pair[1].slope) - i128::from(pair[0].slope)))
+            .collect();
+        Envelope { lines: hull, breakpoints, negated: false }
+    }
+
+    fn lower(lines: &[Line]) -> Self {
+        let negated: Vec<Line> = lines.iter().map(|l| Line { start: -l.start, slope: -l.slope }).collect();
+        Envelope { negated: true, ..Self::upper(&negated) }
+    }
 
-        self.advance_to_step(lo - fudge_steps);
-        self.cur_step
+    /// The line on the envelope at time `t`.
+    fn line_at(&self, t: i64) -> Line {
+        let t = i128::from(t);
+        let index = self.breakpoints.partition_point(|&(num, den)| num < t * den);
+        let line = self.lines[index];
+        if self.negated {
+            Line { start: -line.start, slope: -line.slope }
+        } else {
+            line
+        }
     }
 }
 
-impl Display for ParticleField {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let min_x = self.particles.iter().map(|p| p.x).min().unwrap();
-        let max_x = self.particles.iter().map(|p| p.x).max().unwrap();
-        let min_y = self.particles.iter().map(|p| p.y).min().unwrap();
-        let max_y = self.particles.iter().map(|p| p.y).max().unwrap();
+impl ParticleField {
+    /// Which cells within the particles' bounding box are occupied,
+    /// row by row.
+    fn bitmap(&self) -> Vec<Vec<bool>> {
+        let BoundingBox { min_x, max_x, min_y, max_y } = self.bounding_box();
         let width = max_x - min_x + 1;
         let height = max_y - min_y + 1;
-        let mut array_1d = vec![false; (width * height) as usize];
-        let mut sliced_2d: Vec<_> = array_1d.as_mut_slice().chunks_mut(width as usize).collect();
-        let array_2d = sliced_2d.as_mut_slice();
-
+        let mut rows = vec![vec![false; width as usize]; height as usize];
         for particle in self.particles.iter() {
-            array_2d[(particle.y - min_y) as usize][(particle.x - min_x) as usize] = true;
+            rows[(particle.y - min_y) as usize][(particle.x - min_x) as usize] = true;
         }
+        rows
+    }
+}
 
-        for row in array_2d.iter() {
-            let string: String = row.iter().map(|b| if *b { '#' } else { '.' }).collect();
-            writeln!(f, "{}", string)?;
-        }
-        Ok(())
+impl Display for ParticleField {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", draw(&self.bitmap()))
     }
 }
 
@@ -154,6 +548,7 @@ impl Display for ParticleField {
 mod tests {
     use super::*;
     use std::fmt::Debug;
+    use rand::Rng;
 
     /// This function allows us to assert that a Result is
     /// Ok(expected) without requiring PartialEq on the Error type.
@@ -200,37 +595,37 @@ position=<-3,  6> velocity=< 2, -1>";
     fn test_parse_particles() {
         assert_result_ok(parse_particles(&mut PARTICLES.as_bytes()), ParticleField {
             particles: vec![
-                Particle { x: 9, y: 1, vel_x: 0, vel_y: 2 },
-                Particle { x: 7, y: 0, vel_x: -1, vel_y: 0 },
-                Particle { x: 3, y: -2, vel_x: -1, vel_y: 1 },
-                Particle { x: 6, y: 10, vel_x: -2, vel_y: -1 },
-                Particle { x: 2, y: -4, vel_x: 2, vel_y: 2 },
-                Particle { x: -6, y: 10, vel_x: 2, vel_y: -2 },
-                Particle { x: 1, y: 8, vel_x: 1, vel_y: -1 },
-                Particle { x: 1, y: 7, vel_x: 1, vel_y: 0 },
-                Particle { x: -3, y: 11, vel_x: 1, vel_y: -2 },
-                Particle { x: 7, y: 6, vel_x: -1, vel_y: -1 },
-                Particle { x: -2, y: 3, vel_x: 1, vel_y: 0 },
-                Particle { x: -4, y: 3, vel_x: 2, vel_y: 0 },
-                Particle { x: 10, y: -3, vel_x: -1, vel_y: 1 },
-                Particle { x: 5, y: 11, vel_x: 1, vel_y: -2 },
-                Particle { x: 4, y: 7, vel_x: 0, vel_y: -1 },
-                Particle { x: 8, y: -2, vel_x: 0, vel_y: 1 },
-                Particle { x: 15, y: 0, vel_x: -2, vel_y: 0 },
-                Particle { x: 1, y: 6, vel_x: 1, vel_y: 0 },
-                Particle { x: 8, y: 9, vel_x: 0, vel_y: -1 },
-                Particle { x: 3, y: 3, vel_x: -1, vel_y: 1 },
-                Particle { x: 0, y: 5, vel_x: 0, vel_y: -1 },
-                Particle { x: -2, y: 2, vel_x: 2, vel_y: 0 },
-                Particle { x: 5, y: -2, vel_x: 1, vel_y: 2 },
-                Particle { x: 1, y: 4, vel_x: 2, vel_y: 1 },
-                Particle { x: -2, y: 7, vel_x: 2, vel_y: -2 },
-                Particle { x: 3, y: 6, vel_x: -1, vel_y: -1 },
-                Particle { x: 5, y: 0, vel_x: 1, vel_y: 0 },
-                Particle { x: -6, y: 0, vel_x: 2, vel_y: 0 },
-                Particle { x: 5, y: 9, vel_x: 1, vel_y: -2 },
-                Particle { x: 14, y: 7, vel_x: -2, vel_y: 0 },
-                Particle { x: -3, y: 6, vel_x: 2, vel_y: -1 },
+                Particle { x: 9, y: 1, vel_x: 0, vel_y: 2, acc_x: 0, acc_y: 0 },
+                Particle { x: 7, y: 0, vel_x: -1, vel_y: 0, acc_x: 0, acc_y: 0 },
+                Particle { x: 3, y: -2, vel_x: -1, vel_y: 1, acc_x: 0, acc_y: 0 },
+                Particle { x: 6, y: 10, vel_x: -2, vel_y: -1, acc_x: 0, acc_y: 0 },
+                Particle { x: 2, y: -4, vel_x: 2, vel_y: 2, acc_x: 0, acc_y: 0 },
+                Particle { x: -6, y: 10, vel_x: 2, vel_y: -2, acc_x: 0, acc_y: 0 },
+                Particle { x: 1, y: 8, vel_x: 1, vel_y: -1, acc_x: 0, acc_y: 0 },
+                Particle { x: 1, y: 7, vel_x: 1, vel_y: 0, acc_x: 0, acc_y: 0 },
+                Particle { x: -3, y: 11, vel_x: 1, vel_y: -2, acc_x: 0, acc_y: 0 },
+                Particle { x: 7, y: 6, vel_x: -1, vel_y: -1, acc_x: 0, acc_y: 0 },
+                Particle { x: -2, y: 3, vel_x: 1, vel_y: 0, acc_x: 0, acc_y: 0 },
+                Particle { x: -4, y: 3, vel_x: 2, vel_y: 0, acc_x: 0, acc_y: 0 },
+                Particle { x: 10, y: -3, vel_x: -1, vel_y: 1, acc_x: 0, acc_y: 0 },
+                Particle { x: 5, y: 11, vel_x: 1, vel_y: -2, acc_x: 0, acc_y: 0 },
+                Particle { x: 4, y: 7, vel_x: 0, vel_y: -1, acc_x: 0, acc_y: 0 },
+                Particle { x: 8, y: -2, vel_x: 0, vel_y: 1, acc_x: 0, acc_y: 0 },
+                Particle { x: 15, y: 0, vel_x: -2, vel_y: 0, acc_x: 0, acc_y: 0 },
+                Particle { x: 1, y: 6, vel_x: 1, vel_y: 0, acc_x: 0, acc_y: 0 },
+                Particle { x: 8, y: 9, vel_x: 0, vel_y: -1, acc_x: 0, acc_y: 0 },
+                Particle { x: 3, y: 3, vel_x: -1, vel_y: 1, acc_x: 0, acc_y: 0 },
+                Particle { x: 0, y: 5, vel_x: 0, vel_y: -1, acc_x: 0, acc_y: 0 },
+                Particle { x: -2, y: 2, vel_x: 2, vel_y: 0, acc_x: 0, acc_y: 0 },
+                Particle { x: 5, y: -2, vel_x: 1, vel_y: 2, acc_x: 0, acc_y: 0 },
+                Particle { x: 1, y: 4, vel_x: 2, vel_y: 1, acc_x: 0, acc_y: 0 },
+                Particle { x: -2, y: 7, vel_x: 2, vel_y: -2, acc_x: 0, acc_y: 0 },
+                Particle { x: 3, y: 6, vel_x: -1, vel_y: -1, acc_x: 0, acc_y: 0 },
+                Particle { x: 5, y: 0, vel_x: 1, vel_y: 0, acc_x: 0, acc_y: 0 },
+                Particle { x: -6, y: 0, vel_x: 2, vel_y: 0, acc_x: 0, acc_y: 0 },
+                Particle { x: 5, y: 9, vel_x: 1, vel_y: -2, acc_x: 0, acc_y: 0 },
+                Particle { x: 14, y: 7, vel_x: -2, vel_y: 0, acc_x: 0, acc_y: 0 },
+                Particle { x: -3, y: 6, vel_x: 2, vel_y: -1, acc_x: 0, acc_y: 0 },
             ],
             cur_step: 0,
         })
@@ -274,12 +669,221 @@ position=<-3,  6> velocity=< 2, -1>";
     }
 
     #[test]
-    fn test_particlefield_advance_to_best_distance() {
+    fn test_particlefield_advance_to_convergence() {
         let mut particle_field = parse_particles(&mut PARTICLES.as_bytes()).expect("Couldn't parse particles");
-        assert_eq!(particle_field.advance_to_best_distance(0), 3);
+        let expected_box = BoundingBox { min_x: 0, max_x: 9, min_y: 0, max_y: 7 };
+        assert_eq!(particle_field.advance_to_convergence().unwrap(), Convergence { step: 3, bounding_box: expected_box });
+        assert_eq!(particle_field.bounding_box(), expected_box);
+        // Already converged.
+        assert_eq!(particle_field.advance_to_convergence().unwrap().step, 3);
         particle_field.advance(-10000);
-        assert_eq!(particle_field.advance_to_best_distance(0), 3);
+        assert_eq!(particle_field.advance_to_convergence().unwrap().step, 3);
         particle_field.advance(-100000);
-        assert_eq!(particle_field.advance_to_best_distance(0), 3);
+        assert_eq!(particle_field.advance_to_convergence().unwrap().step, 3);
+        // Past the message, things only spread out.
+        particle_field.advance(1);
+        assert_eq!(particle_field.advance_to_convergence().unwrap().step, 4);
+
+        let empty = ParticleField { particles: vec![], cur_step: 0 };
+        assert!(empty.find_convergence().is_err());
+    }
+
+    #[test]
+    fn test_convergence_matches_brute_force() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..300 {
+            let particles = (0..rng.gen_range(1, 8)).map(|_| Particle {
+                x: rng.gen_range(-50, 50),
+                y: rng.gen_range(-50, 50),
+                vel_x: rng.gen_range(-3, 4),
+                vel_y: rng.gen_range(-3, 4),
+                acc_x: 0,
+                acc_y: 0,
+            }).collect();
+            let mut particle_field = ParticleField { particles, cur_step: 0 };
+            let convergence = particle_field.find_convergence().unwrap();
+            let mut best = (particle_field.bounding_box().area(), 0);
+            for step in 1..300 {
+                particle_field.advance(1);
+                best = std::cmp::min(best, (particle_field.bounding_box().area(), step));
+            }
+            assert_eq!(convergence.step, best.1);
+            assert_eq!(convergence.bounding_box.area(), best.0);
+            particle_field.advance_to_step(convergence.step);
+            assert_eq!(particle_field.bounding_box(), convergence.bounding_box);
+        }
+    }
+
+    #[test]
+    fn test_parse_particles_with_acceleration() {
+        let input = "position=< 3, -2> velocity=<-1,  1> acceleration=< 0, -1>\nposition=< 1,  0> velocity=< 2,  0>";
+        assert_result_ok(parse_particles(&mut input.as_bytes()), ParticleField {
+            particles: vec![
+                Particle { x: 3, y: -2, vel_x: -1, vel_y: 1, acc_x: 0, acc_y: -1 },
+                Particle { x: 1, y: 0, vel_x: 2, vel_y: 0, acc_x: 0, acc_y: 0 },
+            ],
+            cur_step: 0,
+        });
+    }
+
+    /// A random particle with a small velocity and acceleration.
+    fn random_particle(rng: &mut impl Rng) -> Particle {
+        Particle {
+            x: rng.gen_range(-50, 50),
+            y: rng.gen_range(-50, 50),
+            vel_x: rng.gen_range(-3, 4),
+            vel_y: rng.gen_range(-3, 4),
+            acc_x: rng.gen_range(-4, 5),
+            acc_y: rng.gen_range(-4, 5),
+        }
+    }
+
+    #[test]
+    fn test_advance_with_acceleration() {
+        let mut rng = rand::thread_rng();
+        let mut particle_field = ParticleField { particles: (0..20).map(|_| random_particle(&mut rng)).collect(), cur_step: 0 };
+        let mut expected: Vec<(i64, i64, i64, i64)> = particle_field.particles.iter().map(|p| (p.x, p.y, p.vel_x, p.vel_y)).collect();
+        for _ in 0..50 {
+            for (p, (x, y, vel_x, vel_y)) in particle_field.particles.iter().zip(expected.iter_mut()) {
+                *vel_x += p.acc_x;
+                *vel_y += p.acc_y;
+                *x += *vel_x;
+                *y += *vel_y;
+            }
+        }
+        let start: Vec<(i64, i64, i64, i64)> = particle_field.particles.iter().map(|p| (p.x, p.y, p.vel_x, p.vel_y)).collect();
+        particle_field.advance(50);
+        assert_eq!(particle_field.particles.iter().map(|p| (p.x, p.y, p.vel_x, p.vel_y)).collect::<Vec<_>>(), expected);
+        particle_field.advance(-80);
+        particle_field.advance(30);
+        assert_eq!(particle_field.particles.iter().map(|p| (p.x, p.y, p.vel_x, p.vel_y)).collect::<Vec<_>>(), start);
+        assert_eq!(particle_field.cur_step, 0);
+    }
+
+    #[test]
+    fn test_convergence_with_acceleration() {
+        let mut particle_field = parse_particles(&mut PARTICLES.as_bytes()).expect("Couldn't parse particles");
+        for particle in particle_field.particles.iter_mut() {
+            particle.acc_x = 2;
+            particle.acc_y = -1;
+        }
+        let convergence = particle_field.advance_to_convergence().unwrap();
+        assert_eq!(convergence.step, 3);
+        assert_eq!(particle_field.bounding_box(), convergence.bounding_box);
+        assert_eq!(convergence.bounding_box, BoundingBox { min_x: 12, max_x: 21, min_y: -6, max_y: 1 });
+
+        particle_field.particles[0].acc_x = 0;
+        assert!(particle_field.find_convergence().is_err());
+    }
+
+    #[test]
+    fn test_visits() {
+        let input = "position=< 0,  0> velocity=< 1,  0> acceleration=< 0,  1>
+position=<10,  0> velocity=<-1,  0>
+position=< 4, 20> velocity=< 0,  5> acceleration=< 0, -1>";
+        let mut particle_field = parse_particles(&mut input.as_bytes()).expect("Couldn't parse particles");
+        let region = parse_region("6,10,2,0").expect("Couldn't parse region");
+        let visit = |particle, first_step, last_step| RegionVisit { particle, first_step, last_step };
+        // The third particle rises through the region, turns around and
+        // falls back through it.
+        assert_eq!(particle_field.visits(&region, -20, 30), vec![
+            visit(2, -3, -2),
+            visit(0, 2, 4),
+            visit(1, 4, 8),
+            visit(2, 11, 12),
+        ]);
+        assert_eq!(particle_field.visits(&region, 3, 11), vec![visit(0, 3, 4), visit(1, 4, 8), visit(2, 11, 11)]);
+        // Steps are absolute, not relative to where the particles are now.
+        particle_field.advance(5);
+        assert_eq!(particle_field.visits(&region, 3, 11), vec![visit(0, 3, 4), visit(1, 4, 8), visit(2, 11, 11)]);
+        assert_eq!(format!("{}", visit(2, 11, 11)), "Particle 2 is in the region at step 11");
+    }
+
+    #[test]
+    fn test_steps_between_decelerating() {
+        // Peaks at x = 1 at step -1, between the steps either side.
+        assert_eq!(steps_between(0, -1, -3, (1, 1), (-5, 5)), vec![(-1, -1)]);
+        let particle_field = ParticleField {
+            particles: vec![Particle { x: 0, y: 0, vel_x: -1, vel_y: 0, acc_x: -3, acc_y: 0 }],
+            cur_step: 0,
+        };
+        let region = BoundingBox { min_x: 1, max_x: 1, min_y: 0, max_y: 0 };
+        assert_eq!(particle_field.visits(&region, -5, 5), vec![RegionVisit { particle: 0, first_step: -1, last_step: -1 }]);
+    }
+
+    #[test]
+    fn test_visits_match_brute_force() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..1000 {
+            let particle = random_particle(&mut rng);
+            // A small region around where the particle is at some step,
+            // so that it's often only briefly inside.
+            let step = rng.gen_range(-20, 21);
+            let (x, y) = (particle.x + displacement(particle.vel_x, particle.acc_x, step),
+                          particle.y + displacement(particle.vel_y, particle.acc_y, step));
+            let region = BoundingBox {
+                min_x: x - rng.gen_range(0, 3),
+                max_x: x + rng.gen_range(0, 3),
+                min_y: y - rng.gen_range(0, 3),
+                max_y: y + rng.gen_range(0, 3),
+            };
+            let mut particle_field = ParticleField { particles: vec![particle], cur_step: 0 };
+            let visits = particle_field.visits(&region, -20, 20);
+            particle_field.advance(-20);
+            let mut inside = vec![];
+            for step in -20..=20 {
+                let p = &particle_field.particles[0];
+                if (region.min_x..=region.max_x).contains(&p.x) && (region.min_y..=region.max_y).contains(&p.y) {
+                    inside.push(step);
+                }
+                particle_field.advance(1);
+            }
+            let expected: Vec<i64> = visits.iter().flat_map(|v| v.first_step..=v.last_step).collect();
+            assert_eq!(inside, expected);
+        }
+    }
+
+    /// Draws text in a font, with `spacing` empty columns between
+    /// letters.
+    fn render(text: &str, font: &Font, spacing: usize) -> Vec<Vec<bool>> {
+        let mut rows = vec![vec![]; font.height];
+        for (i, letter) in text.chars().enumerate() {
+            let glyph = font.glyphs.iter().find(|(l, _)| *l == letter).unwrap().1;
+            for (row, glyph_row) in rows.iter_mut().zip(glyph) {
+                if i > 0 {
+                    row.extend(std::iter::repeat(false).take(spacing));
+                }
+                row.extend(glyph_row.chars().map(|c| c == '#'));
+            }
+        }
+        rows
+    }
+
+    #[test]
+    fn test_read_message() {
+        let all_small: String = SMALL_FONT.glyphs.iter().map(|(l, _)| *l).collect();
+        assert_eq!(read_message(&render(&all_small, &SMALL_FONT, 1)).text, all_small);
+        let all_large: String = LARGE_FONT.glyphs.iter().map(|(l, _)| *l).collect();
+        assert_eq!(read_message(&render(&all_large, &LARGE_FONT, 2)).text, all_large);
+
+        let mut bitmap = render("HELLO", &SMALL_FONT, 1);
+        // Scribble on the second L.
+        bitmap[0][17] = true;
+        let message = read_message(&bitmap);
+        assert_eq!(message.text, "HEL?O");
+        assert_eq!(message.unknown_glyphs, vec!["#.#.\n#...\n#...\n#...\n#...\n####\n"]);
+    }
+
+    #[test]
+    fn test_read_particle_message() {
+        let mut particle_field = parse_particles(&mut PARTICLES.as_bytes()).expect("Couldn't parse particles");
+        particle_field.advance(3);
+        // The example is 8 rows high, which isn't one of the fonts.
+        let message = read_message(&particle_field.bitmap());
+        assert_eq!(message.text, "??");
+        assert_eq!(message.unknown_glyphs, vec![
+            "#...#\n#...#\n#...#\n#####\n#...#\n#...#\n#...#\n#...#\n",
+            "###\n.#.\n.#.\n.#.\n.#.\n.#.\n.#.\n###\n",
+        ]);
     }
 }
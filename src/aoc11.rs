@@ -1,73 +1,243 @@
 use std::io;
-use std::io::Read;
-use failure::Error;
-
-pub fn aoc11(part2: bool) -> Result<(), Error> {
-    let mut s = String::new();
-    io::stdin().lock().read_to_string(&mut s)?;
-    let serial: usize = s.trim().parse()?;
-    let fc = FuelCells::new(serial);
-    if part2 {
-        println!("Largest total square: {:?}", fc.find_largest_total_square());
+use std::io::{BufRead, Read};
+use std::cmp::Reverse;
+use failure::{Error, ensure};
+use rayon::prelude::*;
+
+/// Finds the best squares of the puzzle's fuel cells, given a serial
+/// number on stdin. With `grid_input`, stdin is instead a grid of
+/// whitespace-separated integers, one row per line, and `rectangle`
+/// finds the best rectangle of any shape.
+pub fn aoc11(part2: bool, rectangle: bool, grid_input: bool, indexing: Indexing) -> Result<(), Error> {
+    let grid = if grid_input {
+        parse_grid(&mut io::stdin().lock())?
+    } else {
+        let mut s = String::new();
+        io::stdin().lock().read_to_string(&mut s)?;
+        fuel_cells(s.trim().parse()?, 300)
+    };
+    if rectangle {
+        match grid.best_rectangle() {
+            Some(best) => {
+                let (x, y) = best.corner(indexing);
+                println!("Largest rectangle: {},{} {}x{} with total power {}", x, y, best.width, best.height, best.sum);
+            },
+            None => println!("The grid is empty"),
+        }
+    } else if part2 {
+        match grid.best_square_any_size() {
+            Some(best) => {
+                let (x, y) = best.corner(indexing);
+                println!("Largest total square: {},{},{} with total power {}", x, y, best.width, best.sum);
+            },
+            None => println!("The grid is empty"),
+        }
     } else {
-        println!("Largest 3x3 square: {:?}", fc.find_largest_square(3, 3));
+        match grid.best_square(3) {
+            Some(best) => {
+                let (x, y) = best.corner(indexing);
+                println!("Largest 3x3 square: {},{} with total power {}", x, y, best.sum);
+            },
+            None => println!("The grid is smaller than 3x3"),
+        }
     }
     Ok(())
 }
 
-struct FuelCells {
-    cells: [[i8; 300]; 300],
-    serial: usize,
+/// Whether coordinates shown to people start from 0 or 1.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Indexing {
+    ZeroBased,
+    OneBased,
+}
+
+/// A rectangle within a grid and the sum of its cells. Coordinates are
+/// always 0-based; use `corner` to show them.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct Subrectangle {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+    pub sum: i64,
+}
+
+impl Subrectangle {
+    /// The top-left corner, in the given indexing.
+    pub fn corner(&self, indexing: Indexing) -> (usize, usize) {
+        match indexing {
+            Indexing::ZeroBased => (self.x, self.y),
+            Indexing::OneBased => (self.x + 1, self.y + 1),
+        }
+    }
+}
+
+/// The sums of every rectangle of a grid anchored at its top-left
+/// corner, from which the sum of any rectangle is O(1).
+pub struct SummedAreaTable {
+    width: usize,
+    height: usize,
+    /// `sums[y * (width + 1) + x]` is the sum of the cells above and to
+    /// the left of `(x, y)`, exclusive, so row and column 0 are zeros.
+    sums: Vec<i64>,
 }
 
-impl FuelCells {
-    fn new(serial: usize) -> Self {
-        let mut fc = FuelCells {
-            cells: [[0; 300]; 300],
-            serial,
-        };
-        for x in 0..300 {
-            for y in 0..300 {
-                fc.cells[x][y] = power_level(x, y, fc.serial);
+impl SummedAreaTable {
+    pub fn new(width: usize, height: usize, cell: impl Fn(usize, usize) -> i64) -> Self {
+        let stride = width + 1;
+        let mut sums = vec![0; stride * (height + 1)];
+        for y in 0..height {
+            let mut row_sum = 0;
+            for x in 0..width {
+                row_sum += cell(x, y);
+                sums[(y + 1) * stride + x + 1] = sums[y * stride + x + 1] + row_sum;
             }
         }
-        fc
-    }
-
-    fn find_largest_square(&self, width: usize, height: usize) -> ((usize, usize), i64) {
-        let mut best_sum = 0;
-        let mut best_index = (0, 0);
-        for x in 0..300-width {
-            for y in 0..300-height {
-                let mut sum: i64 = 0;
-                for x2 in x..x+height {
-                    for y2 in y..y+width {
-                        sum += i64::from(self.cells[x2][y2]);
-                    }
-                }
-                if sum > best_sum {
-                    best_sum = sum;
-                    best_index = (x, y);
+        SummedAreaTable { width, height, sums }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The sum of the `width` by `height` rectangle with its top-left
+    /// corner at `(x, y)`.
+    pub fn rect_sum(&self, x: usize, y: usize, width: usize, height: usize) -> i64 {
+        let stride = self.width + 1;
+        let (x2, y2) = (x + width, y + height);
+        self.sums[y2 * stride + x2] - self.sums[y * stride + x2] - self.sums[y2 * stride + x] + self.sums[y * stride + x]
+    }
+}
+
+/// A 2D grid of integers, stored as its summed-area table.
+pub struct Grid {
+    table: SummedAreaTable,
+}
+
+impl Grid {
+    /// Builds a grid from a function of each cell's 0-based `(x, y)`.
+    pub fn from_fn(width: usize, height: usize, cell: impl Fn(usize, usize) -> i64) -> Self {
+        Grid { table: SummedAreaTable::new(width, height, cell) }
+    }
+
+    pub fn from_rows(rows: &[Vec<i64>]) -> Result<Self, Error> {
+        let width = rows.first().map_or(0, |row| row.len());
+        ensure!(rows.iter().all(|row| row.len() == width), "Grid rows have different lengths");
+        Ok(Self::from_fn(width, rows.len(), |x, y| rows[y][x]))
+    }
+
+    pub fn width(&self) -> usize {
+        self.table.width()
+    }
+
+    pub fn height(&self) -> usize {
+        self.table.height()
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> i64 {
+        self.table.rect_sum(x, y, 1, 1)
+    }
+
+    pub fn rect_sum(&self, x: usize, y: usize, width: usize, height: usize) -> i64 {
+        self.table.rect_sum(x, y, width, height)
+    }
+
+    /// The `size` by `size` square with the largest sum, or `None` if
+    /// there's no room for one. Ties go to the smallest `x`, then `y`.
+    pub fn best_square(&self, size: usize) -> Option<Subrectangle> {
+        if size == 0 || size > self.width() || size > self.height() {
+            return None;
+        }
+        let mut best: Option<Subrectangle> = None;
+        for x in 0..=self.width() - size {
+            for y in 0..=self.height() - size {
+                let sum = self.rect_sum(x, y, size, size);
+                if best.map_or(true, |b| sum > b.sum) {
+                    best = Some(Subrectangle { x, y, width: size, height: size, sum });
                 }
             }
         }
-        (best_index, best_sum)
-    }
-
-    fn find_largest_total_square(&self) -> ((usize, usize), usize) {
-        let mut best_sum = 0;
-        let mut best_size = 0;
-        let mut best_index = (0, 0);
-        for size in 1..=300 {
-            let (index, sum) = self.find_largest_square(size, size);
-            if sum > best_sum {
-                best_index = index;
-                best_size = size;
-                best_sum = sum;
-            }
+        best
+    }
+
+    /// The square of any size with the largest sum, searching every
+    /// size in parallel. The smallest size wins ties.
+    pub fn best_square_any_size(&self) -> Option<Subrectangle> {
+        (1..=self.width().min(self.height())).into_par_iter()
+            .filter_map(|size| self.best_square(size))
+            .max_by_key(|best| (best.sum, Reverse(best.width)))
+    }
+
+    /// The rectangle of any shape with the largest sum, in O(n^2 m)
+    /// time for an n by m grid with n <= m.
+    ///
+    /// For every span of rows (or columns, if there are fewer of them),
+    /// the best run of columns across it is found with Kadane's
+    /// algorithm. If rectangles tie, the one nearest the top left wins.
+    pub fn best_rectangle(&self) -> Option<Subrectangle> {
+        let (width, height) = (self.width(), self.height());
+        if width == 0 || height == 0 {
+            return None;
+        }
+        let transposed = width < height;
+        let (across, along) = if transposed { (width, height) } else { (height, width) };
+        (0..across).into_par_iter()
+            .flat_map_iter(|first| (first..across).map(move |last| (first, last - first + 1)))
+            .map(|(first, span)| {
+                if transposed {
+                    let (start, len, sum) = max_subarray(along, |i| self.rect_sum(first, i, span, 1));
+                    Subrectangle { x: first, y: start, width: span, height: len, sum }
+                } else {
+                    let (start, len, sum) = max_subarray(along, |i| self.rect_sum(i, first, 1, span));
+                    Subrectangle { x: start, y: first, width: len, height: span, sum }
+                }
+            })
+            .max_by_key(|best| (best.sum, Reverse((best.y, best.x, best.height, best.width))))
+    }
+}
+
+/// The non-empty run of `value(0..len)` with the largest sum, as its
+/// start, length and sum.
+fn max_subarray(len: usize, value: impl Fn(usize) -> i64) -> (usize, usize, i64) {
+    let (mut start, mut sum) = (0, value(0));
+    let mut best = (0, 1, sum);
+    for i in 1..len {
+        let v = value(i);
+        if sum < 0 {
+            start = i;
+            sum = v;
+        } else {
+            sum += v;
+        }
+        if sum > best.2 {
+            best = (start, i + 1 - start, sum);
+        }
+    }
+    best
+}
+
+/// Parses whitespace-separated integers, one row per line. Blank lines
+/// are skipped.
+pub fn parse_grid(input: &mut impl BufRead) -> Result<Grid, Error> {
+    let mut rows = vec![];
+    for line_res in input.lines() {
+        let line = line_res?;
+        if line.trim().is_empty() {
+            continue;
         }
-        (best_index, best_size)
+        rows.push(line.split_whitespace().map(|n| n.parse()).collect::<Result<Vec<i64>, _>>()?);
     }
+    Grid::from_rows(&rows)
+}
+
+/// The puzzle's `size` by `size` grid of fuel cells, where the cell at
+/// 0-based `(x, y)` is the one the puzzle calls `(x + 1, y + 1)`.
+pub fn fuel_cells(serial: usize, size: usize) -> Grid {
+    Grid::from_fn(size, size, |x, y| i64::from(power_level(x + 1, y + 1, serial)))
 }
 
 fn power_level(x: usize, y: usize, serial: usize) -> i8 {
@@ -83,6 +253,17 @@ fn power_level(x: usize, y: usize, serial: usize) -> i8 {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::fmt::Debug;
+    use rand::Rng;
+
+    /// This function allows us to assert that a Result is
+    /// Ok(expected) without requiring PartialEq on the Error type.
+    fn assert_result_ok<T: Debug + PartialEq>(r: Result<T, Error>, expected: T) {
+        match r {
+            Ok(v) => assert_eq!(v, expected),
+            Err(e) => panic!("got Err: {}, local backtrace: {}", e, e.backtrace()),
+        }
+    }
 
     #[test]
     fn test_power_level() {
@@ -93,20 +274,112 @@ mod tests {
     }
 
     #[test]
-    fn test_find_largest_square() {
-        let fc = FuelCells::new(18);
-        assert_eq!(fc.find_largest_square(3, 3), ((33, 45), 29));
+    fn test_best_square() {
+        let best = fuel_cells(18, 300).best_square(3).unwrap();
+        assert_eq!((best.corner(Indexing::OneBased), best.sum), ((33, 45), 29));
+        assert_eq!(best.corner(Indexing::ZeroBased), (32, 44));
 
-        let fc = FuelCells::new(42);
-        assert_eq!(fc.find_largest_square(3, 3), ((21, 61), 30));
+        let best = fuel_cells(42, 300).best_square(3).unwrap();
+        assert_eq!((best.corner(Indexing::OneBased), best.sum), ((21, 61), 30));
     }
 
-    #[test] #[ignore]
-    fn test_find_largest_total_square() {
-        let fc = FuelCells::new(18);
-        assert_eq!(fc.find_largest_total_square(), ((90, 269), 16));
+    #[test]
+    fn test_best_square_any_size() {
+        let best = fuel_cells(18, 300).best_square_any_size().unwrap();
+        assert_eq!((best.corner(Indexing::OneBased), best.width, best.sum), ((90, 269), 16, 113));
+
+        let best = fuel_cells(42, 300).best_square_any_size().unwrap();
+        assert_eq!((best.corner(Indexing::OneBased), best.width, best.sum), ((232, 251), 12, 119));
+    }
+
+    #[test]
+    fn test_best_square_at_edges() {
+        let grid = Grid::from_rows(&[
+            vec![-1, -1, -1, -1],
+            vec![-1, -1, -1, -1],
+            vec![-1, -1, 5, 5],
+            vec![-1, -1, 5, 5],
+        ]).unwrap();
+        assert_eq!(grid.best_square(2), Some(Subrectangle { x: 2, y: 2, width: 2, height: 2, sum: 20 }));
+        assert_eq!(grid.best_square(4).map(|b| b.sum), Some(8));
+        assert_eq!(grid.best_square(5), None);
+        assert_eq!(grid.best_square(0), None);
+        // All negative: still the least bad square, not nothing.
+        let grid = Grid::from_fn(3, 2, |x, y| -((x + y) as i64) - 1);
+        assert_eq!(grid.best_square(2), Some(Subrectangle { x: 0, y: 0, width: 2, height: 2, sum: -8 }));
+        assert_eq!(grid.best_square_any_size(), Some(Subrectangle { x: 0, y: 0, width: 1, height: 1, sum: -1 }));
+        assert_eq!(grid.best_rectangle(), Some(Subrectangle { x: 0, y: 0, width: 1, height: 1, sum: -1 }));
+    }
 
-        let fc = FuelCells::new(42);
-        assert_eq!(fc.find_largest_total_square(), ((231, 251), 12));
+    #[test]
+    fn test_best_rectangle() {
+        let grid = Grid::from_rows(&[
+            vec![1, 2, -1, -4, -20],
+            vec![-8, -3, 4, 2, 1],
+            vec![3, 8, 10, 1, 3],
+            vec![-4, -1, 1, 7, -6],
+        ]).unwrap();
+        assert_eq!(grid.best_rectangle(), Some(Subrectangle { x: 1, y: 1, width: 3, height: 3, sum: 29 }));
+        let empty = Grid::from_rows(&[]).unwrap();
+        assert_eq!(empty.best_rectangle(), None);
+        assert_eq!(empty.best_square_any_size(), None);
+    }
+
+    #[test]
+    fn test_best_rectangle_matches_brute_force() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..200 {
+            let (width, height) = (rng.gen_range(1, 8), rng.gen_range(1, 8));
+            let rows: Vec<Vec<i64>> = (0..height).map(|_| (0..width).map(|_| rng.gen_range(-9, 10)).collect()).collect();
+            let grid = Grid::from_rows(&rows).unwrap();
+            let mut best_sum = i64::MIN;
+            for x in 0..width {
+                for y in 0..height {
+                    for w in 1..=width - x {
+                        for h in 1..=height - y {
+                            let sum: i64 = rows[y..y + h].iter().map(|row| row[x..x + w].iter().sum::<i64>()).sum();
+                            best_sum = best_sum.max(sum);
+                        }
+                    }
+                }
+            }
+            let best = grid.best_rectangle().unwrap();
+            assert_eq!(best.sum, best_sum);
+            assert_eq!(grid.rect_sum(best.x, best.y, best.width, best.height), best_sum);
+        }
+    }
+
+    #[test]
+    fn test_parse_grid() {
+        let grid = parse_grid(&mut "1 -2 3\n\n4 5 -6\n".as_bytes()).unwrap();
+        assert_eq!((grid.width(), grid.height(), grid.get(1, 0), grid.get(2, 1)), (3, 2, -2, -6));
+        assert!(parse_grid(&mut "1 2\n3\n".as_bytes()).is_err());
+        assert!(parse_grid(&mut "1 x\n".as_bytes()).is_err());
+        assert_result_ok(parse_grid(&mut "7\n".as_bytes()).map(|g| g.best_rectangle()),
+                         Some(Subrectangle { x: 0, y: 0, width: 1, height: 1, sum: 7 }));
+    }
+
+    #[test]
+    fn test_summed_area_table() {
+        let cell = |x: usize, y: usize| power_level(x, y, 7) as i64 * (x as i64 - 3);
+        let table = SummedAreaTable::new(9, 6, cell);
+        for x in 0..=9 {
+            for y in 0..=6 {
+                for width in 0..=9-x {
+                    for height in 0..=6-y {
+                        let expected: i64 = (x..x+width).flat_map(|x2| (y..y+height).map(move |y2| cell(x2, y2))).sum();
+                        assert_eq!(table.rect_sum(x, y, width, height), expected);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_large_grid() {
+        let grid = fuel_cells(18, 2000);
+        let best = grid.best_square(3).unwrap();
+        assert_eq!(best.sum, grid.rect_sum(best.x, best.y, 3, 3));
+        assert!(best.sum >= 29);
     }
 }
@@ -121,7 +121,7 @@ fn fight(groups: &mut Vec<Group>, boost: u32) -> bool {
         }
         let total_units_before: u32 = groups.iter().map(|g| g.units).sum();
         attack_round(groups, boost);
-        let total_units_after = groups.iter().map(|g| g.units).sum();
+        let total_units_after: u32 = groups.iter().map(|g| g.units).sum();
         if total_units_before == total_units_after {
             // Stuck.
             return false;
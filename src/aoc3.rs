@@ -1,13 +1,13 @@
 use std::io;
 use std::io::BufRead;
-use failure::{Error, bail, ensure};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::ops::Range;
+use std::convert::TryFrom;
+use failure::{Error, bail, ensure, format_err};
 
 pub fn aoc3(part2: bool) -> Result<(), Error> {
-    let stdin = io::stdin();
-    let claims: Vec<Claim> = stdin.lock()
-        .lines()
-        .flat_map(|l_r| l_r.map(|l| parse_claim(&l)))
-        .collect::<Result<_, _>>()?;
+    let claims = read_claims()?;
     if part2 {
         if let Some(claim) = find_intact_claim(&claims) {
             println!("First intact claim found: {}", claim.id);
@@ -21,18 +21,35 @@ pub fn aoc3(part2: bool) -> Result<(), Error> {
     Ok(())
 }
 
+/// Prints the area covered by at least `k` claims.
+pub fn aoc3_at_least(k: &str) -> Result<(), Error> {
+    let k: usize = k.parse()?;
+    ensure!(k > 0, "k must be at least 1");
+    let claims = read_claims()?;
+    println!("Squares covered by at least {} claims: {}", k, area_covered_at_least(&claims, k));
+    Ok(())
+}
+
+fn read_claims() -> Result<Vec<Claim>, Error> {
+    let stdin = io::stdin();
+    stdin.lock()
+        .lines()
+        .flat_map(|l_r| l_r.map(|l| parse_claim(&l)))
+        .collect()
+}
+
 #[derive(PartialEq, Debug)]
-struct Claim {
-    id: u64,
+pub struct Claim {
+    pub id: u64,
     /// Distance from left edge of the fabric to upper left corner of the claim.
-    x: u64,
+    pub x: u64,
     /// Distance from top edge of the fabric to upper left corner of the claim.
-    y: u64,
-    width: u64,
-    height: u64,
+    pub y: u64,
+    pub width: u64,
+    pub height: u64,
 }
 
-fn parse_claim(line: &str) -> Result<Claim, Error> {
+pub fn parse_claim(line: &str) -> Result<Claim, Error> {
     let fields: Vec<_> = line.split(' ').collect();
     ensure!(fields.len() == 4, "Incorrect number of fields in claim");
     // Field 1: ID
@@ -60,6 +77,9 @@ fn parse_claim(line: &str) -> Result<Claim, Error> {
     ensure!(width_height.len() == 2, "Too many values for width/height");
     let width = width_height[0];
     let height = width_height[1];
+    // Everything else can then compute the far edges without overflow.
+    ensure!(x.checked_add(width).is_some() && y.checked_add(height).is_some(),
+            "Claim extends past the largest coordinate");
     Ok(Claim {
         id,
         x,
@@ -89,18 +109,20 @@ impl Fabric {
         }
     }
 
-    /// Get the index of the u8 that contains our square.
-    fn index(&self, width: usize, height: usize) -> usize {
-        width * self.height / 8 + height / 8
+    /// Get the index of the u8 that contains our square, and the
+    /// mask for its bit within that u8.
+    fn index(&self, width: usize, height: usize) -> (usize, u8) {
+        let bit = width * self.height + height;
+        (bit / 8, 0x1 << (bit % 8))
     }
 
     /// Add a unit of coverage for a single square.
     fn add_coverage(&mut self, width: usize, height: usize) {
-        let index = self.index(width, height);
-        let covered_once = self.covered_once[index] & (0x1 << (height % 8));
-        self.covered_once[index] |= 0x1 << (height % 8);
+        let (index, mask) = self.index(width, height);
+        let covered_once = self.covered_once[index] & mask;
+        self.covered_once[index] |= mask;
         if covered_once > 0 {
-            self.covered_twice[index] |= 0x1 << (height % 8);
+            self.covered_twice[index] |= mask;
         }
     }
 
@@ -117,9 +139,9 @@ impl Fabric {
 
     /// Is this square covered once (but not twice)?
     fn covered_exactly_once(&self, width: usize, height: usize) -> bool {
-        let index = self.index(width, height);
-        let covered_once = self.covered_once[index] & (0x1 << (height % 8));
-        let covered_twice = self.covered_twice[index] & (0x1 << (height % 8));
+        let (index, mask) = self.index(width, height);
+        let covered_once = self.covered_once[index] & mask;
+        let covered_twice = self.covered_twice[index] & mask;
         covered_once > 0 && covered_twice == 0
     }
 
@@ -136,15 +158,590 @@ fn create_minimal_fabric(claims: &[Claim]) -> Fabric {
     Fabric::new(fabric_width as usize, fabric_height as usize)
 }
 
+/// A rectangular window onto the fabric.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Window {
+    pub x: u64,
+    pub y: u64,
+    pub width: u64,
+    pub height: u64,
+}
+
+/// Characters used for the ASCII heatmap, from uncovered to most
+/// heavily contested.
+const HEAT_RAMP: &[u8] = b" .:-=+*#%@";
+
+/// Full coverage multiplicity for every square inch within a window
+/// of the fabric.
+pub struct CoverageCounts {
+    window: Window,
+    /// Row-major counts of how many claims cover each square.
+    counts: Vec<u32>,
+}
+
+impl CoverageCounts {
+    /// Count coverage within the given window, or within the minimal
+    /// fabric if no window is given. Minimal fabrics bigger than the
+    /// dense engine would allocate need an explicit window.
+    pub fn new(claims: &[Claim], window: Option<Window>) -> Result<Self, Error> {
+        let explicit = window.is_some();
+        let window = window.unwrap_or_else(|| Window {
+            x: 0,
+            y: 0,
+            width: claims.iter().map(|c| c.x + c.width).max().unwrap_or(0),
+            height: claims.iter().map(|c| c.y + c.height).max().unwrap_or(0),
+        });
+        ensure!(window.x.checked_add(window.width).is_some() && window.y.checked_add(window.height).is_some(),
+                "Window extends past the largest coordinate");
+        let area = window.width.checked_mul(window.height)
+            .ok_or_else(|| format_err!("Window of {}x{} is too big", window.width, window.height))?;
+        ensure!(explicit || area <= DENSE_FABRIC_LIMIT,
+                "Fabric of {}x{} is too big to count in full; pick a window", window.width, window.height);
+        let mut coverage = CoverageCounts {
+            window,
+            counts: vec![0; usize::try_from(area)?],
+        };
+        for claim in claims {
+            if let Some((xs, ys)) = coverage.clip(claim) {
+                for y in ys {
+                    for x in xs.clone() {
+                        let index = coverage.index(x, y);
+                        coverage.counts[index] += 1;
+                    }
+                }
+            }
+        }
+        Ok(coverage)
+    }
+
+    /// The part of the claim that falls within the window, in window
+    /// coordinates.
+    fn clip(&self, claim: &Claim) -> Option<(Range<u64>, Range<u64>)> {
+        let left = claim.x.max(self.window.x);
+        let right = (claim.x + claim.width).min(self.window.x + self.window.width);
+        let top = claim.y.max(self.window.y);
+        let bottom = (claim.y + claim.height).min(self.window.y + self.window.height);
+        if left >= right || top >= bottom {
+            return None;
+        }
+        Some((left - self.window.x..right - self.window.x,
+              top - self.window.y..bottom - self.window.y))
+    }
+
+    /// Index of a square, given in window coordinates.
+    fn index(&self, x: u64, y: u64) -> usize {
+        (y * self.window.width + x) as usize
+    }
+
+    /// Number of claims covering a square (in fabric coordinates).
+    pub fn count(&self, x: u64, y: u64) -> u32 {
+        if x < self.window.x || x >= self.window.x + self.window.width
+            || y < self.window.y || y >= self.window.y + self.window.height {
+            return 0;
+        }
+        self.counts[self.index(x - self.window.x, y - self.window.y)]
+    }
+
+    /// Highest coverage of any square in the window.
+    pub fn max_count(&self) -> u32 {
+        self.counts.iter().cloned().max().unwrap_or(0)
+    }
+
+    /// Render the window as ASCII, one character per square, with
+    /// denser characters for more heavily covered squares.
+    pub fn to_ascii(&self) -> String {
+        let max_count = self.max_count();
+        let levels = (HEAT_RAMP.len() - 2) as u64;
+        let mut out = String::new();
+        for row in self.counts.chunks(self.window.width.max(1) as usize) {
+            for &count in row {
+                let level = if count == 0 {
+                    0
+                } else {
+                    1 + u64::from(count - 1) * levels / u64::from(max_count.max(2) - 1)
+                };
+                out.push(HEAT_RAMP[level as usize] as char);
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    /// Render the window as a binary greyscale PGM image, with
+    /// brighter pixels for more heavily covered squares, and the
+    /// outlines of the given claims drawn in white.
+    pub fn to_pgm(&self, outlines: &[Claim]) -> Vec<u8> {
+        let max_count = self.max_count();
+        let mut pixels: Vec<u8> = self.counts.iter()
+            .map(|&count| if count == 0 {
+                0
+            } else {
+                (32 + u64::from(count - 1) * 191 / u64::from(max_count.max(2) - 1)) as u8
+            })
+            .collect();
+        for claim in outlines {
+            if let Some((xs, ys)) = self.clip(claim) {
+                let left = claim.x.max(self.window.x) == claim.x;
+                let right = claim.x + claim.width <= self.window.x + self.window.width;
+                let top = claim.y.max(self.window.y) == claim.y;
+                let bottom = claim.y + claim.height <= self.window.y + self.window.height;
+                for x in xs.clone() {
+                    if top {
+                        pixels[self.index(x, ys.start)] = 255;
+                    }
+                    if bottom {
+                        pixels[self.index(x, ys.end - 1)] = 255;
+                    }
+                }
+                for y in ys {
+                    if left {
+                        pixels[self.index(xs.start, y)] = 255;
+                    }
+                    if right {
+                        pixels[self.index(xs.end - 1, y)] = 255;
+                    }
+                }
+            }
+        }
+        let mut image = format!("P5\n{} {}\n255\n", self.window.width, self.window.height).into_bytes();
+        image.extend(pixels);
+        image
+    }
+}
+
+/// Largest fabric (in square inches) we're willing to allocate the
+/// dense bitmaps for. Anything bigger goes through the sparse engine.
+const DENSE_FABRIC_LIMIT: u64 = 1 << 26;
+
+/// The two ways we know of to compute claim overlaps.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Engine {
+    /// Bitmaps covering every square inch of the fabric. Fast, but
+    /// only usable when the fabric is small.
+    Dense,
+    /// Sweep line over coordinate-compressed claim edges. Memory and
+    /// time depend only on the number of claims.
+    Sparse,
+}
+
+impl Engine {
+    /// Pick the dense engine if the minimal fabric is small enough,
+    /// and the sparse engine otherwise.
+    fn for_claims(claims: &[Claim]) -> Self {
+        let fabric_width = claims.iter().map(|c| c.x + c.width).max().unwrap_or(0);
+        let fabric_height = claims.iter().map(|c| c.y + c.height).max().unwrap_or(0);
+        match fabric_width.checked_mul(fabric_height) {
+            Some(area) if area <= DENSE_FABRIC_LIMIT => Engine::Dense,
+            _ => Engine::Sparse,
+        }
+    }
+}
+
+/// Segment tree over the compressed y-coordinates of the claims,
+/// tracking how much of each node's span is covered by at least 1, 2,
+/// ..., k claims.
+struct CoverageTree {
+    /// Sorted, deduplicated y-coordinates. Leaf i spans ys[i]..ys[i + 1].
+    ys: Vec<u64>,
+    k: usize,
+    /// Number of claims covering the whole span of each node (that
+    /// aren't already counted at one of its ancestors).
+    cover: Vec<usize>,
+    /// lengths[node * k + j] is the length of the node's span covered
+    /// by at least j + 1 claims.
+    lengths: Vec<u64>,
+}
+
+impl CoverageTree {
+    fn new(ys: Vec<u64>, k: usize) -> Self {
+        let num_nodes = 4 * ys.len().max(1);
+        CoverageTree {
+            ys,
+            k,
+            cover: vec![0; num_nodes],
+            lengths: vec![0; num_nodes * k],
+        }
+    }
+
+    /// Number of elementary intervals (leaves) in the tree.
+    fn num_leaves(&self) -> usize {
+        self.ys.len().saturating_sub(1)
+    }
+
+    /// Add (or remove) one claim's coverage over y1..y2.
+    fn update(&mut self, y1: u64, y2: u64, add: bool) {
+        let l = self.ys.binary_search(&y1).expect("y1 not in compressed coordinates");
+        let r = self.ys.binary_search(&y2).expect("y2 not in compressed coordinates");
+        let num_leaves = self.num_leaves();
+        self.update_node(1, 0, num_leaves, l, r, add);
+    }
+
+    fn update_node(&mut self, node: usize, lo: usize, hi: usize, l: usize, r: usize, add: bool) {
+        if r <= lo || hi <= l {
+            return;
+        }
+        if l <= lo && hi <= r {
+            if add {
+                self.cover[node] += 1;
+            } else {
+                self.cover[node] -= 1;
+            }
+        } else {
+            let mid = (lo + hi) / 2;
+            self.update_node(2 * node, lo, mid, l, r, add);
+            self.update_node(2 * node + 1, mid, hi, l, r, add);
+        }
+        self.pull(node, lo, hi);
+    }
+
+    /// Recompute a node's covered lengths from its cover count and
+    /// its children.
+    fn pull(&mut self, node: usize, lo: usize, hi: usize) {
+        let span = self.ys[hi] - self.ys[lo];
+        let cover = self.cover[node];
+        for j in 0..self.k {
+            let needed = j + 1;
+            self.lengths[node * self.k + j] = if cover >= needed {
+                span
+            } else if hi - lo == 1 {
+                0
+            } else {
+                let remaining = needed - cover - 1;
+                self.lengths[2 * node * self.k + remaining]
+                    + self.lengths[(2 * node + 1) * self.k + remaining]
+            };
+        }
+    }
+
+    /// Length of the whole y-axis covered by at least k claims.
+    fn covered_at_least_k(&self) -> u64 {
+        self.lengths[self.k + self.k - 1]
+    }
+}
+
+/// Segment tree holding one value per claim, indexed by the claim's
+/// rank when sorted by top edge. Supports point updates and finding
+/// all values above a threshold within a prefix.
+struct MaxTree {
+    size: usize,
+    max: Vec<u64>,
+}
+
+impl MaxTree {
+    fn new(size: usize) -> Self {
+        MaxTree {
+            size,
+            max: vec![0; 4 * size.max(1)],
+        }
+    }
+
+    fn set(&mut self, index: usize, value: u64) {
+        self.set_node(1, 0, self.size, index, value);
+    }
+
+    fn set_node(&mut self, node: usize, lo: usize, hi: usize, index: usize, value: u64) {
+        if hi - lo == 1 {
+            self.max[node] = value;
+            return;
+        }
+        let mid = (lo + hi) / 2;
+        if index < mid {
+            self.set_node(2 * node, lo, mid, index, value);
+        } else {
+            self.set_node(2 * node + 1, mid, hi, index, value);
+        }
+        self.max[node] = self.max[2 * node].max(self.max[2 * node + 1]);
+    }
+
+    /// Maximum value among indices 0..end.
+    fn prefix_max(&self, end: usize) -> u64 {
+        self.prefix_max_node(1, 0, self.size, end)
+    }
+
+    fn prefix_max_node(&self, node: usize, lo: usize, hi: usize, end: usize) -> u64 {
+        if end <= lo {
+            0
+        } else if hi <= end {
+            self.max[node]
+        } else {
+            let mid = (lo + hi) / 2;
+            self.prefix_max_node(2 * node, lo, mid, end)
+                .max(self.prefix_max_node(2 * node + 1, mid, hi, end))
+        }
+    }
+
+    /// Collect all indices in 0..end with a value greater than threshold.
+    fn find_greater(&self, end: usize, threshold: u64, found: &mut Vec<usize>) {
+        self.find_greater_node(1, 0, self.size, end, threshold, found);
+    }
+
+    fn find_greater_node(&self, node: usize, lo: usize, hi: usize, end: usize,
+                         threshold: u64, found: &mut Vec<usize>) {
+        if end <= lo || self.max[node] <= threshold {
+            return;
+        }
+        if hi - lo == 1 {
+            found.push(lo);
+            return;
+        }
+        let mid = (lo + hi) / 2;
+        self.find_greater_node(2 * node, lo, mid, end, threshold, found);
+        self.find_greater_node(2 * node + 1, mid, hi, end, threshold, found);
+    }
+}
+
+/// Sweep-line event: a claim's left edge (start) or right edge (end)
+/// at a given x-coordinate.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug)]
+struct SweepEvent {
+    x: u64,
+    /// Ends sort before starts, since claims are half-open.
+    is_start: bool,
+    claim_index: usize,
+}
+
+/// Build the sweep events for every claim with a non-zero area.
+fn sweep_events(claims: &[Claim]) -> Vec<SweepEvent> {
+    let mut events: Vec<SweepEvent> = claims.iter()
+        .enumerate()
+        .filter(|(_, c)| c.width > 0 && c.height > 0)
+        .flat_map(|(i, c)| vec![
+            SweepEvent { x: c.x, is_start: true, claim_index: i },
+            SweepEvent { x: c.x + c.width, is_start: false, claim_index: i },
+        ])
+        .collect();
+    events.sort();
+    events
+}
+
+/// Total area covered by k or more claims, using the sparse engine.
+pub fn area_covered_at_least(claims: &[Claim], k: usize) -> u128 {
+    assert!(k > 0, "k must be at least 1");
+    let mut ys: Vec<u64> = claims.iter()
+        .filter(|c| c.width > 0 && c.height > 0)
+        .flat_map(|c| vec![c.y, c.y + c.height])
+        .collect();
+    ys.sort();
+    ys.dedup();
+    let mut tree = CoverageTree::new(ys, k);
+    let mut area: u128 = 0;
+    let mut last_x = 0;
+    for event in sweep_events(claims) {
+        area += u128::from(tree.covered_at_least_k()) * u128::from(event.x - last_x);
+        last_x = event.x;
+        let claim = &claims[event.claim_index];
+        tree.update(claim.y, claim.y + claim.height, event.is_start);
+    }
+    area
+}
+
+/// The claims sorted by their top edge, which is the index space
+/// used by the MaxTrees in the sweep.
+struct TopOrder {
+    /// Claim indices, sorted by top edge.
+    by_top: Vec<usize>,
+    /// Position of each claim within by_top.
+    rank: Vec<usize>,
+    /// Top edge of each claim in by_top.
+    tops: Vec<u64>,
+}
+
+impl TopOrder {
+    fn new(claims: &[Claim]) -> Self {
+        let mut by_top: Vec<usize> = (0..claims.len()).collect();
+        by_top.sort_by_key(|&i| claims[i].y);
+        let mut rank = vec![0; claims.len()];
+        for (r, &i) in by_top.iter().enumerate() {
+            rank[i] = r;
+        }
+        let tops = by_top.iter().map(|&i| claims[i].y).collect();
+        TopOrder { by_top, rank, tops }
+    }
+}
+
+/// Find which claims overlap no other claim, using the sparse engine.
+///
+/// Every overlapping pair is noticed when the second of the two
+/// claims enters the sweep. At that point the newcomer is checked
+/// against all active claims, and any not-yet-flagged active claims it
+/// touches are flagged and dropped from the search, so each claim is
+/// only ever found once.
+fn find_intact_claims_sparse(claims: &[Claim]) -> Vec<bool> {
+    let TopOrder { by_top, rank, tops } = TopOrder::new(claims);
+
+    // Both trees store each claim's bottom edge at its rank, or 0
+    // if it isn't present.
+    let mut active = MaxTree::new(claims.len());
+    let mut unflagged = MaxTree::new(claims.len());
+    let mut intact = vec![true; claims.len()];
+    let mut found = Vec::new();
+    for event in sweep_events(claims) {
+        let claim = &claims[event.claim_index];
+        let claim_rank = rank[event.claim_index];
+        if !event.is_start {
+            active.set(claim_rank, 0);
+            unflagged.set(claim_rank, 0);
+            continue;
+        }
+        let bottom = claim.y + claim.height;
+        // Active claims intersect this one iff their top is above our
+        // bottom and their bottom is below our top.
+        let end = tops.partition_point(|&top| top < bottom);
+        if active.prefix_max(end) > claim.y {
+            intact[event.claim_index] = false;
+        }
+        found.clear();
+        unflagged.find_greater(end, claim.y, &mut found);
+        for &r in &found {
+            intact[by_top[r]] = false;
+            unflagged.set(r, 0);
+        }
+        active.set(claim_rank, bottom);
+        if intact[event.claim_index] {
+            unflagged.set(claim_rank, bottom);
+        }
+    }
+    intact
+}
+
+impl Claim {
+    /// Does this claim cover the given square inch?
+    pub fn covers(&self, x: u64, y: u64) -> bool {
+        x >= self.x && x < self.x + self.width && y >= self.y && y < self.y + self.height
+    }
+
+    /// Area shared between this claim and another.
+    pub fn overlap_area(&self, other: &Claim) -> u128 {
+        let left = self.x.max(other.x);
+        let right = (self.x + self.width).min(other.x + other.width);
+        let top = self.y.max(other.y);
+        let bottom = (self.y + self.height).min(other.y + other.height);
+        if left >= right || top >= bottom {
+            return 0;
+        }
+        u128::from(right - left) * u128::from(bottom - top)
+    }
+}
+
+/// All claims covering the given square inch.
+pub fn claims_covering(claims: &[Claim], x: u64, y: u64) -> Vec<&Claim> {
+    claims.iter().filter(|c| c.covers(x, y)).collect()
+}
+
+/// All other claims overlapping the claim with the given ID, along
+/// with the area of each overlap.
+pub fn overlapping_claims(claims: &[Claim], id: u64) -> Vec<(&Claim, u128)> {
+    let claim = match claims.iter().find(|c| c.id == id) {
+        Some(claim) => claim,
+        None => return vec![],
+    };
+    claims.iter()
+        .filter(|c| !std::ptr::eq(*c, claim))
+        .map(|c| (c, c.overlap_area(claim)))
+        .filter(|&(_, area)| area > 0)
+        .collect()
+}
+
+/// Which claims overlap which, and by how much.
+#[derive(PartialEq, Debug)]
+pub struct ConflictGraph {
+    /// For every claim ID, the IDs of the claims it overlaps and the
+    /// area of each overlap. Claims without conflicts have an empty
+    /// list.
+    pub edges: BTreeMap<u64, Vec<(u64, u128)>>,
+}
+
+impl ConflictGraph {
+    /// Build the graph with a sweep line, so the work done is
+    /// proportional to the number of conflicts rather than the number
+    /// of pairs of claims.
+    pub fn new(claims: &[Claim]) -> Self {
+        let TopOrder { by_top, rank, tops } = TopOrder::new(claims);
+        let mut edges: BTreeMap<u64, Vec<(u64, u128)>> = claims.iter()
+            .map(|c| (c.id, vec![]))
+            .collect();
+        let mut active = MaxTree::new(claims.len());
+        let mut found = Vec::new();
+        for event in sweep_events(claims) {
+            let claim = &claims[event.claim_index];
+            let claim_rank = rank[event.claim_index];
+            if !event.is_start {
+                active.set(claim_rank, 0);
+                continue;
+            }
+            let bottom = claim.y + claim.height;
+            let end = tops.partition_point(|&top| top < bottom);
+            found.clear();
+            active.find_greater(end, claim.y, &mut found);
+            for &r in &found {
+                let other = &claims[by_top[r]];
+                let area = claim.overlap_area(other);
+                edges.entry(claim.id).or_default().push((other.id, area));
+                edges.entry(other.id).or_default().push((claim.id, area));
+            }
+            active.set(claim_rank, bottom);
+        }
+        for neighbors in edges.values_mut() {
+            neighbors.sort();
+        }
+        ConflictGraph { edges }
+    }
+
+    /// Render as an adjacency list, one claim per line, like
+    /// "1: 2 (4), 3 (1)", with the overlap area in parentheses.
+    pub fn to_adjacency_list(&self) -> String {
+        let mut out = String::new();
+        for (id, neighbors) in &self.edges {
+            let neighbors: Vec<String> = neighbors.iter()
+                .map(|(other, area)| format!("{} ({})", other, area))
+                .collect();
+            writeln!(out, "{}: {}", id, neighbors.join(", ")).unwrap();
+        }
+        out
+    }
+
+    /// Render as an undirected Graphviz graph, with edges labelled by
+    /// overlap area.
+    pub fn to_dot(&self) -> String {
+        let mut out = String::new();
+        writeln!(out, "graph conflicts {{").unwrap();
+        for id in self.edges.keys() {
+            writeln!(out, "    {};", id).unwrap();
+        }
+        for (id, neighbors) in &self.edges {
+            for (other, area) in neighbors.iter().filter(|(other, _)| id < other) {
+                writeln!(out, "    {} -- {} [label=\"{}\"];", id, other, area).unwrap();
+            }
+        }
+        writeln!(out, "}}").unwrap();
+        out
+    }
+}
+
 /// Implements part 1.
-fn calculate_overlap(claims: &[Claim]) -> u64 {
-    let mut fabric = create_minimal_fabric(claims);
-    fabric.add_claims(claims);
-    fabric.total_overlap()
+fn calculate_overlap(claims: &[Claim]) -> u128 {
+    match Engine::for_claims(claims) {
+        Engine::Dense => {
+            let mut fabric = create_minimal_fabric(claims);
+            fabric.add_claims(claims);
+            u128::from(fabric.total_overlap())
+        },
+        Engine::Sparse => area_covered_at_least(claims, 2),
+    }
 }
 
 /// Implements part 2.
 fn find_intact_claim(claims: &[Claim]) -> Option<&Claim> {
+    match Engine::for_claims(claims) {
+        Engine::Dense => find_intact_claim_dense(claims),
+        Engine::Sparse => {
+            let intact = find_intact_claims_sparse(claims);
+            claims.iter().zip(intact).find(|(_, i)| *i).map(|(c, _)| c)
+        },
+    }
+}
+
+fn find_intact_claim_dense(claims: &[Claim]) -> Option<&Claim> {
     let mut fabric = create_minimal_fabric(claims);
     fabric.add_claims(claims);
     'claim_loop: for claim in claims {
@@ -164,6 +761,7 @@ fn find_intact_claim(claims: &[Claim]) -> Option<&Claim> {
 mod tests {
     use super::*;
     use std::fmt::Debug;
+    use rand::Rng;
 
     /// This function allows us to assert that a Result is
     /// Ok(expected) without requiring PartialEq on the Error type.
@@ -183,6 +781,9 @@ mod tests {
             width: 5,
             height: 4,
         });
+        assert!(parse_claim("#1 @ 18446744073709551615,0: 2x2").is_err());
+        assert!(parse_claim("#1 @ 0,18446744073709551614: 2x2").is_err());
+        assert_result_ok(parse_claim("#1 @ 18446744073709551613,0: 2x2").map(|c| c.x + c.width), u64::MAX);
     }
 
     #[test]
@@ -240,4 +841,180 @@ mod tests {
         ];
         assert_eq!(find_intact_claim(&claims), Some(&claims[2]));
     }
+
+    #[test]
+    fn test_sparse_engine() {
+        let claims = vec![
+            Claim {
+                id: 1,
+                x: 1,
+                y: 3,
+                width: 4,
+                height: 4,
+            },
+            Claim {
+                id: 2,
+                x: 3,
+                y: 1,
+                width: 4,
+                height: 4,
+            },
+            Claim {
+                id: 3,
+                x: 5,
+                y: 5,
+                width: 2,
+                height: 2,
+            },
+        ];
+        assert_eq!(area_covered_at_least(&claims, 1), 32);
+        assert_eq!(area_covered_at_least(&claims, 2), 4);
+        assert_eq!(area_covered_at_least(&claims, 3), 0);
+        assert_eq!(find_intact_claims_sparse(&claims), vec![false, false, true]);
+    }
+
+    #[test]
+    fn test_huge_offsets_use_sparse_engine() {
+        let offset = 1_000_000_000_000;
+        let claims = vec![
+            Claim {
+                id: 1,
+                x: offset,
+                y: offset,
+                width: 1_000_000,
+                height: 1_000_000,
+            },
+            Claim {
+                id: 2,
+                x: offset + 999_999,
+                y: offset + 500_000,
+                width: 10,
+                height: 10,
+            },
+            Claim {
+                id: 3,
+                x: 0,
+                y: 0,
+                width: 3,
+                height: 3,
+            },
+        ];
+        assert_eq!(Engine::for_claims(&claims), Engine::Sparse);
+        assert_eq!(calculate_overlap(&claims), 10);
+        assert_eq!(find_intact_claim(&claims), Some(&claims[2]));
+    }
+
+    #[test]
+    fn test_sparse_engine_matches_dense() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..50 {
+            let claims: Vec<Claim> = (0..rng.gen_range(1, 30))
+                .map(|id| Claim {
+                    id,
+                    x: rng.gen_range(0, 50),
+                    y: rng.gen_range(0, 50),
+                    width: rng.gen_range(0, 15),
+                    height: rng.gen_range(0, 15),
+                })
+                .collect();
+            let mut fabric = create_minimal_fabric(&claims);
+            fabric.add_claims(&claims);
+            assert_eq!(area_covered_at_least(&claims, 2), u128::from(fabric.total_overlap()));
+            let sparse_intact = find_intact_claims_sparse(&claims);
+            let first_sparse = claims.iter().zip(sparse_intact).find(|(_, i)| *i).map(|(c, _)| c);
+            assert_eq!(first_sparse, find_intact_claim_dense(&claims));
+        }
+    }
+
+    #[test]
+    fn test_conflict_queries() {
+        let claims = vec![
+            parse_claim("#1 @ 1,3: 4x4").unwrap(),
+            parse_claim("#2 @ 3,1: 4x4").unwrap(),
+            parse_claim("#3 @ 5,5: 2x2").unwrap(),
+            parse_claim("#4 @ 2,4: 1x1").unwrap(),
+        ];
+        assert_eq!(claims_covering(&claims, 3, 3), vec![&claims[0], &claims[1]]);
+        assert_eq!(claims_covering(&claims, 0, 0), Vec::<&Claim>::new());
+        assert_eq!(overlapping_claims(&claims, 1), vec![(&claims[1], 4), (&claims[3], 1)]);
+        assert_eq!(overlapping_claims(&claims, 3), vec![]);
+
+        let graph = ConflictGraph::new(&claims);
+        assert_eq!(graph.to_adjacency_list(), "1: 2 (4), 4 (1)\n2: 1 (4)\n3: \n4: 1 (1)\n");
+        assert_eq!(graph.to_dot(), "graph conflicts {
+    1;
+    2;
+    3;
+    4;
+    1 -- 2 [label=\"4\"];
+    1 -- 4 [label=\"1\"];
+}
+");
+    }
+
+    #[test]
+    fn test_conflict_graph_matches_brute_force() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..50 {
+            let claims: Vec<Claim> = (0..rng.gen_range(1, 30))
+                .map(|id| Claim {
+                    id,
+                    x: rng.gen_range(0, 50),
+                    y: rng.gen_range(0, 50),
+                    width: rng.gen_range(0, 15),
+                    height: rng.gen_range(0, 15),
+                })
+                .collect();
+            let graph = ConflictGraph::new(&claims);
+            for claim in &claims {
+                let expected: Vec<(u64, u128)> = overlapping_claims(&claims, claim.id)
+                    .into_iter()
+                    .map(|(c, area)| (c.id, area))
+                    .collect();
+                assert_eq!(graph.edges[&claim.id], expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_coverage_counts() {
+        let claims = vec![
+            parse_claim("#1 @ 1,3: 4x4").unwrap(),
+            parse_claim("#2 @ 3,1: 4x4").unwrap(),
+            parse_claim("#3 @ 5,5: 2x2").unwrap(),
+            parse_claim("#4 @ 3,3: 1x1").unwrap(),
+        ];
+        let coverage = CoverageCounts::new(&claims, None).unwrap();
+        assert_eq!(coverage.count(3, 3), 3);
+        assert_eq!(coverage.count(0, 0), 0);
+        assert_eq!(coverage.max_count(), 3);
+        assert_eq!(coverage.to_ascii(),
+                   "       \n   ....\n   ....\n ..@+..\n ..++..\n ......\n ......\n");
+
+        let window = Window { x: 2, y: 2, width: 3, height: 2 };
+        let cropped = CoverageCounts::new(&claims, Some(window)).unwrap();
+        assert_eq!(cropped.count(3, 3), 3);
+        assert_eq!(cropped.count(0, 0), 0);
+        assert_eq!(cropped.to_ascii(), " ..\n.@+\n");
+        let image = cropped.to_pgm(&claims[3..]);
+        assert_eq!(&image[..11], b"P5\n3 2\n255\n");
+        assert_eq!(&image[11..], &[0, 32, 32, 32, 255, 127]);
+    }
+
+    #[test]
+    fn test_coverage_counts_far_claims() {
+        let claims = vec![
+            parse_claim("#1 @ 1000000000,1000000000: 2x2").unwrap(),
+            parse_claim("#2 @ 1000000001,1000000001: 2x2").unwrap(),
+        ];
+        assert!(CoverageCounts::new(&claims, None).is_err());
+        let window = Window { x: 1000000000, y: 1000000000, width: 3, height: 3 };
+        let coverage = CoverageCounts::new(&claims, Some(window)).unwrap();
+        assert_eq!(coverage.count(1000000001, 1000000001), 2);
+        assert_eq!(coverage.to_ascii(), ".. \n.@.\n ..\n");
+        let huge = Window { x: 0, y: 0, width: u64::MAX, height: 2 };
+        assert!(CoverageCounts::new(&claims, Some(huge)).is_err());
+        let past_the_end = Window { x: u64::MAX, y: 0, width: 1, height: 1 };
+        assert!(CoverageCounts::new(&claims, Some(past_the_end)).is_err());
+    }
 }
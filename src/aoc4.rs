@@ -1,156 +1,508 @@
 use std::io;
 use std::io::BufRead;
 use std::collections::BTreeMap;
-use failure::{Error, format_err, bail};
+use std::fmt;
+use std::fmt::Display;
+use failure::{Error, format_err, bail, ensure};
 use regex::Regex;
 use std::ops::Range;
 
+/// Print the full per-guard sleep report, as either a "table" or "csv".
+pub fn aoc4_report(format: &str) -> Result<(), Error> {
+    let stdin = io::stdin();
+    let log = parse_shifts(stdin.lock())?;
+    for anomaly in &log.anomalies {
+        eprintln!("Warning: {}", anomaly);
+    }
+    let report = SleepReport::new(&log.shifts);
+    match format {
+        "table" => print!("{}", report.to_table()),
+        "csv" => print!("{}", report.to_csv()),
+        _ => bail!("Unknown report format {}", format),
+    }
+    Ok(())
+}
+
 pub fn aoc4(part2: bool) -> Result<(), Error> {
     let stdin = io::stdin();
-    let sorted_string = sort_times(stdin.lock())?;
-    let shifts = parse_shifts(sorted_string.as_bytes())?;
+    let log = parse_shifts(stdin.lock())?;
+    for anomaly in &log.anomalies {
+        eprintln!("Warning: {}", anomaly);
+    }
+    let report = SleepReport::new(&log.shifts);
     if part2 {
-        let (guard_id, minute) = find_sleepy_minute_all_guards(&shifts);
-        println!("ID: {}, minute: {}, product: {}", guard_id, minute, (guard_id as usize) * minute);
+        let sleeps = report.most_frequent_sleeps();
+        ensure!(!sleeps.is_empty(), "No guard ever fell asleep");
+        for (guard_id, minute) in sleeps {
+            println!("ID: {}, minute: {}, product: {}", guard_id, minute, (guard_id as usize) * minute);
+        }
     } else {
-        let guard_id = find_sleepiest_guard(&shifts).ok_or_else(|| format_err!("No sleepiest guard found"))?;
-        let sleepiest_minute = u64::from(find_sleepiest_minute(&shifts, guard_id));
-        println!("ID: {}, minute: {}, product: {}", guard_id, sleepiest_minute, guard_id * sleepiest_minute);
+        let guard_ids = report.sleepiest_guards();
+        ensure!(!guard_ids.is_empty(), "No sleepiest guard found");
+        for guard_id in guard_ids {
+            for minute in report.guard(guard_id).unwrap().sleepiest_minutes() {
+                println!("ID: {}, minute: {}, product: {}", guard_id, minute, guard_id * minute as u64);
+            }
+        }
     }
     Ok(())
 }
 
+/// A day in the (proleptic) Gregorian calendar.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+struct Date {
+    year: i64,
+    month: u32,
+    day: u32,
+}
+
+impl Date {
+    /// Number of days in this date's month.
+    fn days_in_month(self) -> u32 {
+        match self.month {
+            4 | 6 | 9 | 11 => 30,
+            2 if self.year % 4 == 0 && (self.year % 100 != 0 || self.year % 400 == 0) => 29,
+            2 => 28,
+            _ => 31,
+        }
+    }
+
+    /// Number of days since 1970-01-01 (negative for earlier dates).
+    fn days_since_epoch(self) -> i64 {
+        let month = i64::from(self.month);
+        let year = if month <= 2 { self.year - 1 } else { self.year };
+        let era = year.div_euclid(400);
+        let year_of_era = year - era * 400;
+        let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + i64::from(self.day) - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        era * 146_097 + day_of_era - 719_468
+    }
+
+    /// Inverse of days_since_epoch.
+    fn from_days_since_epoch(days: i64) -> Self {
+        let days = days + 719_468;
+        let era = days.div_euclid(146_097);
+        let day_of_era = days - era * 146_097;
+        let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let shifted_month = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+        let month = (shifted_month + 2) % 12 + 1;
+        let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+        Date { year, month: month as u32, day: day as u32 }
+    }
+
+    fn next_day(self) -> Self {
+        Date::from_days_since_epoch(self.days_since_epoch() + 1)
+    }
+
+    /// Day of the week, from 0 (Monday) to 6 (Sunday).
+    fn weekday(self) -> usize {
+        // 1970-01-01 was a Thursday.
+        (self.days_since_epoch() + 3).rem_euclid(7) as usize
+    }
+}
+
+impl Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+/// A date and time, to the minute.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+struct DateTime {
+    date: Date,
+    hour: u32,
+    minute: u32,
+}
+
+impl DateTime {
+    /// Number of minutes since 1970-01-01 00:00.
+    fn minutes_since_epoch(self) -> i64 {
+        self.date.days_since_epoch() * 24 * 60 + i64::from(self.hour * 60 + self.minute)
+    }
+
+    /// Number of minutes from this time until a later one.
+    fn minutes_until(self, later: DateTime) -> i64 {
+        later.minutes_since_epoch() - self.minutes_since_epoch()
+    }
+}
+
+impl Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {:02}:{:02}", self.date, self.hour, self.minute)
+    }
+}
+
 #[derive(Debug, PartialEq)]
 struct GuardShift {
     id: u64,
-    sleeps: Vec<Range<u8>>
+    /// The day this shift covers. Shifts that begin in the evening
+    /// belong to the following day.
+    date: Date,
+    /// When the guard began the shift.
+    start: DateTime,
+    sleeps: Vec<Range<DateTime>>,
 }
 
-/// Find the ID of a guard that spent the most time asleep. None if there were no guards.
-fn find_sleepiest_guard(shifts: &[GuardShift]) -> Option<u64> {
-    let mut guard_to_time_asleep: BTreeMap<u64, usize> = BTreeMap::new();
-    for shift in shifts {
-        let guard_id = shift.id;
-        let time_asleep: usize = shift.sleeps.iter().map(|sleep| sleep.len()).sum();
-        *guard_to_time_asleep.entry(guard_id).or_insert(0) += time_asleep;
+impl GuardShift {
+    fn new(id: u64, start: DateTime) -> Self {
+        let date = if start.hour >= 12 {
+            start.date.next_day()
+        } else {
+            start.date
+        };
+        GuardShift { id, date, start, sleeps: vec![] }
     }
-    guard_to_time_asleep.iter().max_by_key(|(_k, v)| *v).map(|(k, _v)| *k)
 }
 
-fn find_sleepy_minute_all_guards(shifts: &[GuardShift]) -> (u64, usize) {
-    let mut guard_to_minutes: BTreeMap<u64, [u64; 60]> = BTreeMap::new();
-    for shift in shifts {
-        let minutes = guard_to_minutes.entry(shift.id).or_insert([0; 60]);
+/// Total number of minutes spent in a sleep.
+fn sleep_length(sleep: &Range<DateTime>) -> usize {
+    sleep.start.minutes_until(sleep.end) as usize
+}
+
+/// The minute of the hour for each minute spent in a sleep.
+fn minutes_asleep(sleep: &Range<DateTime>) -> impl Iterator<Item = usize> {
+    let start = sleep.start.minutes_since_epoch();
+    let end = sleep.end.minutes_since_epoch();
+    (start..end).map(|minute| minute.rem_euclid(60) as usize)
+}
+
+const WEEKDAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+
+/// Sleep statistics for a single guard.
+#[derive(Debug, PartialEq)]
+struct GuardReport {
+    id: u64,
+    shifts: usize,
+    total_minutes_asleep: usize,
+    /// Number of shifts in which the guard was asleep at each minute
+    /// of the hour.
+    minute_histogram: [usize; 60],
+    /// Minutes asleep on shifts for each day of the week, starting
+    /// with Monday.
+    weekday_minutes: [usize; 7],
+    /// Longest single sleep (the earliest, if several are equally
+    /// long). None if the guard never slept.
+    longest_nap: Option<Range<DateTime>>,
+}
+
+impl GuardReport {
+    fn new(id: u64) -> Self {
+        GuardReport {
+            id,
+            shifts: 0,
+            total_minutes_asleep: 0,
+            minute_histogram: [0; 60],
+            weekday_minutes: [0; 7],
+            longest_nap: None,
+        }
+    }
+
+    fn add_shift(&mut self, shift: &GuardShift) {
+        self.shifts += 1;
+        let mut asleep_at = [false; 60];
         for sleep in &shift.sleeps {
-            for minute in sleep.clone() {
-                minutes[minute as usize] += 1;
+            let length = sleep_length(sleep);
+            self.total_minutes_asleep += length;
+            self.weekday_minutes[shift.date.weekday()] += length;
+            let is_longest = match &self.longest_nap {
+                Some(nap) => length > sleep_length(nap),
+                None => true,
+            };
+            if is_longest {
+                self.longest_nap = Some(sleep.clone());
+            }
+            for minute in minutes_asleep(sleep) {
+                asleep_at[minute] = true;
+            }
+        }
+        for (count, asleep) in self.minute_histogram.iter_mut().zip(asleep_at.iter()) {
+            if *asleep {
+                *count += 1;
             }
         }
     }
-    let (id, (min, _asleep)) = guard_to_minutes
-        .iter()
-        .map(|(id, v)| (id, v.iter().enumerate().max_by_key(|(_minute, asleep)| *asleep).unwrap()))
-        .max_by_key(|(_id, (_minute, asleep))| *asleep)
-        .unwrap();
-    (*id, min)
+
+    /// Fraction of this guard's shifts in which they were asleep at
+    /// the given minute.
+    fn asleep_probability(&self, minute: usize) -> f64 {
+        if self.shifts == 0 {
+            return 0.0;
+        }
+        self.minute_histogram[minute] as f64 / self.shifts as f64
+    }
+
+    /// The minutes this guard was most often asleep at, in order. Empty
+    /// if the guard never slept.
+    fn sleepiest_minutes(&self) -> Vec<usize> {
+        let max = self.minute_histogram.iter().cloned().max().unwrap_or(0);
+        if max == 0 {
+            return vec![];
+        }
+        (0..60).filter(|&minute| self.minute_histogram[minute] == max).collect()
+    }
 }
 
-fn find_sleepiest_minute(shifts: &[GuardShift], guard_id: u64) -> u8 {
-    let mut minutes = [0; 60];
-    for shift in shifts.iter().filter(|g| g.id == guard_id) {
-        for sleep in &shift.sleeps {
-            for minute in sleep.clone() {
-                minutes[minute as usize] += 1;
+/// Sleep statistics for every guard, ordered by guard ID.
+#[derive(Debug, PartialEq)]
+struct SleepReport {
+    guards: Vec<GuardReport>,
+}
+
+impl SleepReport {
+    fn new(shifts: &[GuardShift]) -> Self {
+        let mut guards: BTreeMap<u64, GuardReport> = BTreeMap::new();
+        for shift in shifts {
+            guards.entry(shift.id)
+                .or_insert_with(|| GuardReport::new(shift.id))
+                .add_shift(shift);
+        }
+        SleepReport { guards: guards.into_values().collect() }
+    }
+
+    fn guard(&self, id: u64) -> Option<&GuardReport> {
+        self.guards.iter().find(|g| g.id == id)
+    }
+
+    /// IDs of the guards that spent the most time asleep. Empty if no
+    /// guard ever slept.
+    fn sleepiest_guards(&self) -> Vec<u64> {
+        let max = self.guards.iter().map(|g| g.total_minutes_asleep).max().unwrap_or(0);
+        if max == 0 {
+            return vec![];
+        }
+        self.guards.iter().filter(|g| g.total_minutes_asleep == max).map(|g| g.id).collect()
+    }
+
+    /// The (guard ID, minute) pairs where a guard was most frequently
+    /// asleep on the same minute. Empty if no guard ever slept.
+    fn most_frequent_sleeps(&self) -> Vec<(u64, usize)> {
+        let max = self.guards.iter()
+            .flat_map(|g| g.minute_histogram.iter().cloned())
+            .max()
+            .unwrap_or(0);
+        if max == 0 {
+            return vec![];
+        }
+        self.guards.iter()
+            .flat_map(|g| (0..60).filter(move |&m| g.minute_histogram[m] == max).map(move |m| (g.id, m)))
+            .collect()
+    }
+
+    /// Render a summary of each guard as a plain-text table.
+    fn to_table(&self) -> String {
+        let mut out = format!("{:>6} {:>6} {:>6} {:>6} ", "Guard", "Shifts", "Asleep", "Nap");
+        for weekday in &WEEKDAYS {
+            out += &format!("{:>5} ", weekday);
+        }
+        out += "Sleepiest minutes\n";
+        for guard in &self.guards {
+            let longest_nap = guard.longest_nap.as_ref().map_or(0, sleep_length);
+            out += &format!("{:>6} {:>6} {:>6} {:>6} ", guard.id, guard.shifts,
+                            guard.total_minutes_asleep, longest_nap);
+            for minutes in &guard.weekday_minutes {
+                out += &format!("{:>5} ", minutes);
             }
+            let sleepiest: Vec<String> = guard.sleepiest_minutes().iter()
+                .map(|&m| format!("{} ({:.0}%)", m, 100.0 * guard.asleep_probability(m)))
+                .collect();
+            out += &sleepiest.join(", ");
+            out += "\n";
         }
+        out
+    }
+
+    /// Render every statistic for each guard as CSV, with the
+    /// probability of being asleep at each minute in the last 60
+    /// columns.
+    fn to_csv(&self) -> String {
+        let mut header = vec!["guard".to_string(), "shifts".to_string(),
+                              "minutes_asleep".to_string(), "longest_nap".to_string()];
+        header.extend(WEEKDAYS.iter().map(|d| d.to_lowercase()));
+        header.extend((0..60).map(|m| format!("minute_{}", m)));
+        let mut out = header.join(",") + "\n";
+        for guard in &self.guards {
+            let mut row = vec![guard.id.to_string(), guard.shifts.to_string(),
+                               guard.total_minutes_asleep.to_string(),
+                               guard.longest_nap.as_ref().map_or(0, sleep_length).to_string()];
+            row.extend(guard.weekday_minutes.iter().map(|m| m.to_string()));
+            row.extend((0..60).map(|m| format!("{:.3}", guard.asleep_probability(m))));
+            out += &row.join(",");
+            out += "\n";
+        }
+        out
     }
-    minutes.iter().enumerate().max_by_key(|(_i, value)| *value).map(|(i, _value)| i as u8).unwrap()
 }
 
-type Time = (u32, u32, u32, u32, u32);
+#[derive(Debug, PartialEq)]
+enum EventKind {
+    BeginsShift(u64),
+    FallsAsleep,
+    WakesUp,
+}
 
-fn sort_times(input: impl BufRead) -> Result<String, Error> {
-    let time_regex = Regex::new(r"\[(\d+)-(\d+)-(\d+) (\d+):(\d+)\]")?;
-    let mut lines_and_times: Vec<(Time, String)> = vec![];
-    for line_result in input.lines() {
-        let line = line_result?;
-        if line.is_empty() {
-            continue;
+/// A single line of the log.
+#[derive(Debug, PartialEq)]
+struct Event {
+    /// Line number (starting from 1) in the original input.
+    line: usize,
+    time: DateTime,
+    kind: EventKind,
+}
+
+/// Something in the log that doesn't make sense, and was skipped.
+#[derive(Debug, PartialEq)]
+enum Anomaly {
+    /// The guard fell asleep while already asleep.
+    FellAsleepTwice { line: usize, guard_id: u64 },
+    /// The guard woke up without having fallen asleep.
+    WokeWithoutSleeping { line: usize, guard_id: u64 },
+    /// The guard fell asleep, but the shift (or the log) ended
+    /// before they woke up. The sleep is discarded.
+    MissingWakeUp { line: usize, guard_id: u64 },
+    /// Someone fell asleep or woke up before any guard began a shift.
+    NoGuardOnDuty { line: usize },
+}
+
+impl Display for Anomaly {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Anomaly::FellAsleepTwice { line, guard_id } =>
+                write!(f, "line {}: guard #{} fell asleep while already asleep", line, guard_id),
+            Anomaly::WokeWithoutSleeping { line, guard_id } =>
+                write!(f, "line {}: guard #{} woke up without falling asleep", line, guard_id),
+            Anomaly::MissingWakeUp { line, guard_id } =>
+                write!(f, "line {}: guard #{} fell asleep and never woke up", line, guard_id),
+            Anomaly::NoGuardOnDuty { line } =>
+                write!(f, "line {}: event before any guard began a shift", line),
         }
-        let time_captures = time_regex.captures(&line).ok_or_else(|| format_err!("no time in line {}", line))?;
-        let time_parts: Time = (time_captures[1].parse().unwrap(),
-                                time_captures[2].parse().unwrap(),
-                                time_captures[3].parse().unwrap(),
-                                time_captures[4].parse().unwrap(),
-                                time_captures[5].parse().unwrap());
-        lines_and_times.push((time_parts, line.clone()));
     }
-    lines_and_times.sort();
-    let combined_string: String = lines_and_times.into_iter().map(|(_, str)| str).collect::<Vec<_>>().join("\n");
-    Ok(combined_string)
 }
 
-fn parse_shifts(input: impl BufRead) -> Result<Vec<GuardShift>, Error> {
-    let min_regex = Regex::new(r"\[\d+-\d+-\d+ \d+:(?P<min>\d+)\]")?;
-    let begins_shift = Regex::new(r"Guard #(?P<id>\d+) begins shift$")?;
+/// All the shifts in a log, plus anything odd we noticed along the way.
+#[derive(Debug, PartialEq)]
+struct ShiftLog {
+    shifts: Vec<GuardShift>,
+    anomalies: Vec<Anomaly>,
+}
 
-    let mut shifts = vec![];
-    // Guard on the current shift
-    let mut guard_id: Option<u64> = None;
-    // Start of the last sleep, or None if not asleep
-    let mut sleep_start: Option<u8> = None;
-    // Sleeps for the current guard
-    let mut cur_sleeps = vec![];
-    for line_result in input.lines() {
+/// Parses individual log lines into events.
+struct EventParser {
+    event_regex: Regex,
+    begins_shift_regex: Regex,
+}
+
+impl EventParser {
+    fn new() -> Result<Self, Error> {
+        Ok(EventParser {
+            event_regex: Regex::new(r"^\[(\d+)-(\d+)-(\d+) (\d+):(\d+)\] (.*)$")?,
+            begins_shift_regex: Regex::new(r"^Guard #(\d+) begins shift$")?,
+        })
+    }
+
+    fn parse(&self, line_number: usize, line: &str) -> Result<Event, Error> {
+        let captures = self.event_regex.captures(line)
+            .ok_or_else(|| format_err!("no time in line {}: {}", line_number, line))?;
+        let time = DateTime {
+            date: Date {
+                year: captures[1].parse()?,
+                month: captures[2].parse()?,
+                day: captures[3].parse()?,
+            },
+            hour: captures[4].parse()?,
+            minute: captures[5].parse()?,
+        };
+        ensure!((1..=12).contains(&time.date.month) && (1..=time.date.days_in_month()).contains(&time.date.day)
+                && time.hour < 24 && time.minute < 60,
+                "invalid time in line {}: {}", line_number, line);
+        let kind = if let Some(shift_captures) = self.begins_shift_regex.captures(&captures[6]) {
+            EventKind::BeginsShift(shift_captures[1].parse()?)
+        } else if &captures[6] == "falls asleep" {
+            EventKind::FallsAsleep
+        } else if &captures[6] == "wakes up" {
+            EventKind::WakesUp
+        } else {
+            bail!("Line {} didn't match any expected pattern: {}", line_number, line);
+        };
+        Ok(Event { line: line_number, time, kind })
+    }
+}
+
+/// Parse the log (in any order) into shifts.
+fn parse_shifts(input: impl BufRead) -> Result<ShiftLog, Error> {
+    let parser = EventParser::new()?;
+    let mut events = vec![];
+    for (i, line_result) in input.lines().enumerate() {
         let line = line_result?;
         if line.is_empty() {
             // Empty line, skip
             continue
         }
-        let captures = min_regex.captures(&line).ok_or_else(|| format_err!("no time in line {}", line))?;
-        let minute: u8 = captures[1].parse()?;
-        if let Some(captures) = begins_shift.captures(&line) {
-            if let Some(prev_guard_id) = guard_id {
-                // Finish previous guard's shift.
-                shifts.push(GuardShift { id: prev_guard_id, sleeps: cur_sleeps });
-                cur_sleeps = vec![];
-            }
-            guard_id = Some(captures[1].parse()?);
-        } else if line.ends_with("falls asleep") {
-            sleep_start = Some(minute);
-        } else if line.ends_with("wakes up") {
-            let start = sleep_start.ok_or_else(|| format_err!("Guard {:?} woke up before falling asleep", guard_id))?;
-            cur_sleeps.push(start..minute);
-            sleep_start = None;
-        } else {
-            bail!("Line {} didn't match any expected pattern", line);
+        events.push(parser.parse(i + 1, &line)?);
+    }
+    // Stable, so simultaneous events stay in the order they were logged.
+    events.sort_by_key(|e| e.time);
+    Ok(build_shifts(&events))
+}
+
+/// Group time-sorted events into shifts, checking that each guard's
+/// sleeps and wake-ups alternate.
+fn build_shifts(events: &[Event]) -> ShiftLog {
+    let mut shifts = vec![];
+    let mut anomalies = vec![];
+    // Guard on the current shift
+    let mut cur_shift: Option<GuardShift> = None;
+    // Line and time the guard fell asleep, or None if not asleep
+    let mut sleep_start: Option<(usize, DateTime)> = None;
+    for event in events {
+        match (&event.kind, cur_shift.as_mut()) {
+            (EventKind::BeginsShift(id), _) => {
+                if let Some(prev_shift) = cur_shift.take() {
+                    // Finish previous guard's shift.
+                    if let Some((line, _)) = sleep_start.take() {
+                        anomalies.push(Anomaly::MissingWakeUp { line, guard_id: prev_shift.id });
+                    }
+                    shifts.push(prev_shift);
+                }
+                cur_shift = Some(GuardShift::new(*id, event.time));
+            },
+            (_, None) => anomalies.push(Anomaly::NoGuardOnDuty { line: event.line }),
+            (EventKind::FallsAsleep, Some(shift)) => {
+                if sleep_start.is_some() {
+                    anomalies.push(Anomaly::FellAsleepTwice { line: event.line, guard_id: shift.id });
+                } else {
+                    sleep_start = Some((event.line, event.time));
+                }
+            },
+            (EventKind::WakesUp, Some(shift)) => {
+                if let Some((_, start)) = sleep_start.take() {
+                    shift.sleeps.push(start..event.time);
+                } else {
+                    anomalies.push(Anomaly::WokeWithoutSleeping { line: event.line, guard_id: shift.id });
+                }
+            },
         }
     }
 
-    if let Some(prev_guard_id) = guard_id {
+    if let Some(prev_shift) = cur_shift {
         // Finish last guard's shift.
-        shifts.push(GuardShift { id: prev_guard_id, sleeps: cur_sleeps });
+        if let Some((line, _)) = sleep_start {
+            anomalies.push(Anomaly::MissingWakeUp { line, guard_id: prev_shift.id });
+        }
+        shifts.push(prev_shift);
     }
 
-    Ok(shifts)
+    ShiftLog { shifts, anomalies }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::fmt::Debug;
     use rand::prelude::SliceRandom;
     use rand::thread_rng;
 
-    /// This function allows us to assert that a Result is
-    /// Ok(expected) without requiring PartialEq on the Error type.
-    fn assert_result_ok<T: Debug + PartialEq>(r: Result<T, Error>, expected: T) {
-        match r {
-            Ok(v) => assert_eq!(v, expected),
-            Err(e) => panic!("got Err: {}, local backtrace: {}", e, e.backtrace()),
-        }
-    }
-
     const TIME_STRING: &'static str = "
 [1518-11-01 00:00] Guard #10 begins shift
 [1518-11-01 00:05] falls asleep
@@ -171,59 +523,166 @@ mod tests {
 [1518-11-05 00:55] wakes up
 ";
 
-    #[test]
-    fn test_parse_shifts() {
-        assert_result_ok(parse_shifts(TIME_STRING.as_bytes()), vec![
-            GuardShift {
-                id: 10,
-                sleeps: vec![(5..25), (30..55)],
-            },
-            GuardShift {
-                id: 99,
-                sleeps: vec![(40..50)],
-            },
-            GuardShift {
-                id: 10,
-                sleeps: vec![(24..29)],
-            },
-            GuardShift {
-                id: 99,
-                sleeps: vec![(36..46)],
-            },
-            GuardShift {
-                id: 99,
-                sleeps: vec![(45..55)],
-            },
-        ])
+    fn time(day: u32, hour: u32, minute: u32) -> DateTime {
+        DateTime {
+            date: Date { year: 1518, month: 11, day },
+            hour,
+            minute,
+        }
     }
 
     #[test]
-    fn test_find_sleepiest_minute() {
-        let shifts = parse_shifts(TIME_STRING.as_bytes()).expect("Can't parse shifts");
-        assert_eq!(find_sleepiest_minute(&shifts, 10), 24);
+    fn test_parse_shifts() {
+        let log = parse_shifts(TIME_STRING.as_bytes()).expect("Can't parse shifts");
+        assert_eq!(log.anomalies, vec![]);
+        assert_eq!(log.shifts.len(), 5);
+        assert_eq!(log.shifts[0], GuardShift {
+            id: 10,
+            date: Date { year: 1518, month: 11, day: 1 },
+            start: time(1, 0, 0),
+            sleeps: vec![(time(1, 0, 5)..time(1, 0, 25)), (time(1, 0, 30)..time(1, 0, 55))],
+        });
+        assert_eq!(log.shifts[1], GuardShift {
+            id: 99,
+            date: Date { year: 1518, month: 11, day: 2 },
+            start: time(1, 23, 58),
+            sleeps: vec![(time(2, 0, 40)..time(2, 0, 50))],
+        });
+        let ids: Vec<u64> = log.shifts.iter().map(|s| s.id).collect();
+        assert_eq!(ids, vec![10, 99, 10, 99, 99]);
     }
 
     #[test]
-    fn test_find_sleepiest_guard() {
-        let shifts = parse_shifts(TIME_STRING.as_bytes()).expect("Can't parse shifts");
-        assert_eq!(find_sleepiest_guard(&shifts), Some(10));
+    fn test_parse_invalid_dates() {
+        let parser = EventParser::new().unwrap();
+        for date in &["1518-02-30", "1518-02-29", "1518-04-31", "1518-11-31", "1518-13-01", "1518-01-00"] {
+            assert!(parser.parse(1, &format!("[{} 00:00] wakes up", date)).is_err(), "{} was accepted", date);
+        }
+        for date in &["1516-02-29", "2000-02-29", "1518-01-31", "1518-04-30"] {
+            assert!(parser.parse(1, &format!("[{} 00:00] wakes up", date)).is_ok(), "{} was rejected", date);
+        }
+        assert!(parser.parse(1, "[1500-02-29 00:00] wakes up").is_err());
     }
 
     #[test]
-    fn test_sort_times() {
+    fn test_parse_shuffled_shifts() {
         let mut lines: Vec<_> = TIME_STRING.lines().collect();
         let mut rng = thread_rng();
         lines.shuffle(&mut rng);
         let shuffled_string: String = lines.join("\n");
-        let sorted = sort_times(shuffled_string.as_bytes());
-        assert_eq!(sorted.unwrap(), TIME_STRING.trim());
+        let shuffled = parse_shifts(shuffled_string.as_bytes()).expect("Can't parse shifts");
+        let sorted = parse_shifts(TIME_STRING.as_bytes()).expect("Can't parse shifts");
+        assert_eq!(shuffled.shifts, sorted.shifts);
+    }
+
+    #[test]
+    fn test_anomalies() {
+        let log = parse_shifts("[1518-10-31 23:00] wakes up
+[1518-11-01 00:00] Guard #10 begins shift
+[1518-11-01 00:05] falls asleep
+[1518-11-01 00:10] falls asleep
+[1518-11-01 00:25] wakes up
+[1518-11-01 00:26] wakes up
+[1518-11-01 00:30] falls asleep
+[1518-11-02 00:00] Guard #99 begins shift
+[1518-11-02 23:50] falls asleep
+[1518-11-03 00:05] wakes up
+[1518-11-03 00:10] falls asleep".as_bytes()).expect("Can't parse shifts");
+        assert_eq!(log.anomalies, vec![
+            Anomaly::NoGuardOnDuty { line: 1 },
+            Anomaly::FellAsleepTwice { line: 4, guard_id: 10 },
+            Anomaly::WokeWithoutSleeping { line: 6, guard_id: 10 },
+            Anomaly::MissingWakeUp { line: 7, guard_id: 10 },
+            Anomaly::MissingWakeUp { line: 11, guard_id: 99 },
+        ]);
+        assert_eq!(log.anomalies[1].to_string(), "line 4: guard #10 fell asleep while already asleep");
+        assert_eq!(log.shifts[0].sleeps, vec![time(1, 0, 5)..time(1, 0, 25)]);
+        // A sleep spanning midnight counts the minutes before midnight too.
+        let sleep = &log.shifts[1].sleeps[0];
+        assert_eq!(sleep_length(sleep), 15);
+        assert_eq!(minutes_asleep(sleep).collect::<Vec<_>>(),
+                   vec![50, 51, 52, 53, 54, 55, 56, 57, 58, 59, 0, 1, 2, 3, 4]);
     }
 
     #[test]
-    fn test_find_sleepy_minute_all_guards() {
-        let shifts = parse_shifts(TIME_STRING.as_bytes()).expect("Can't parse shifts");
-        let (guard_id, minute) = find_sleepy_minute_all_guards(&shifts);
-        assert_eq!(guard_id, 99);
-        assert_eq!(minute, 45);
+    fn test_dates() {
+        assert_eq!(Date { year: 1970, month: 1, day: 1 }.days_since_epoch(), 0);
+        assert_eq!(Date { year: 1969, month: 12, day: 31 }.days_since_epoch(), -1);
+        assert_eq!(Date { year: 1518, month: 12, day: 31 }.next_day(), Date { year: 1519, month: 1, day: 1 });
+        assert_eq!(Date { year: 1600, month: 2, day: 28 }.next_day(), Date { year: 1600, month: 2, day: 29 });
+        assert_eq!(Date { year: 1700, month: 2, day: 28 }.next_day(), Date { year: 1700, month: 3, day: 1 });
+        for days in -200_000..1000 {
+            assert_eq!(Date::from_days_since_epoch(days).days_since_epoch(), days);
+        }
+    }
+
+    #[test]
+    fn test_sleepiest_minutes() {
+        let shifts = parse_shifts(TIME_STRING.as_bytes()).expect("Can't parse shifts").shifts;
+        let report = SleepReport::new(&shifts);
+        assert_eq!(report.guard(10).unwrap().sleepiest_minutes(), vec![24]);
+    }
+
+    #[test]
+    fn test_sleepiest_guards() {
+        let shifts = parse_shifts(TIME_STRING.as_bytes()).expect("Can't parse shifts").shifts;
+        let report = SleepReport::new(&shifts);
+        assert_eq!(report.sleepiest_guards(), vec![10]);
+    }
+
+    #[test]
+    fn test_most_frequent_sleeps() {
+        let shifts = parse_shifts(TIME_STRING.as_bytes()).expect("Can't parse shifts").shifts;
+        let report = SleepReport::new(&shifts);
+        assert_eq!(report.most_frequent_sleeps(), vec![(99, 45)]);
+    }
+
+    #[test]
+    fn test_sleep_report() {
+        let shifts = parse_shifts(TIME_STRING.as_bytes()).expect("Can't parse shifts").shifts;
+        let report = SleepReport::new(&shifts);
+        let guard = report.guard(99).unwrap();
+        assert_eq!(guard.shifts, 3);
+        assert_eq!(guard.total_minutes_asleep, 30);
+        assert_eq!(guard.longest_nap, Some(time(2, 0, 40)..time(2, 0, 50)));
+        assert_eq!(guard.weekday_minutes, [10, 10, 0, 0, 0, 10, 0]);
+        assert_eq!(guard.sleepiest_minutes(), vec![45]);
+        assert!((guard.asleep_probability(45) - 1.0).abs() < 1e-9);
+        assert!((guard.asleep_probability(36) - 1.0 / 3.0).abs() < 1e-9);
+        assert_eq!(guard.asleep_probability(0), 0.0);
+
+        let table = report.to_table();
+        assert_eq!(table.lines().next().unwrap(),
+                   " Guard Shifts Asleep    Nap   Mon   Tue   Wed   Thu   Fri   Sat   Sun Sleepiest minutes");
+        assert_eq!(table.lines().nth(2).unwrap(),
+                   "    99      3     30     10    10    10     0     0     0    10     0 45 (100%)");
+        let csv = report.to_csv();
+        let rows: Vec<Vec<&str>> = csv.lines().map(|l| l.split(',').collect()).collect();
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0].len(), 71);
+        assert_eq!(rows[0][..5], ["guard", "shifts", "minutes_asleep", "longest_nap", "mon"]);
+        assert_eq!(rows[2][..4], ["99", "3", "30", "10"]);
+        assert_eq!(rows[2][11 + 45], "1.000");
+    }
+
+    #[test]
+    fn test_ties() {
+        let log = parse_shifts("[1518-11-01 00:00] Guard #10 begins shift
+[1518-11-01 00:05] falls asleep
+[1518-11-01 00:07] wakes up
+[1518-11-01 00:20] falls asleep
+[1518-11-01 00:21] wakes up
+[1518-11-02 00:00] Guard #3 begins shift
+[1518-11-02 00:30] falls asleep
+[1518-11-02 00:33] wakes up
+[1518-11-03 00:00] Guard #7 begins shift".as_bytes()).expect("Can't parse shifts");
+        let report = SleepReport::new(&log.shifts);
+        assert_eq!(report.sleepiest_guards(), vec![3, 10]);
+        assert_eq!(report.guard(10).unwrap().sleepiest_minutes(), vec![5, 6, 20]);
+        assert_eq!(report.guard(7).unwrap().sleepiest_minutes(), Vec::<usize>::new());
+        assert_eq!(report.guard(7).unwrap().longest_nap, None);
+        assert_eq!(report.most_frequent_sleeps(),
+                   vec![(3, 30), (3, 31), (3, 32), (10, 5), (10, 6), (10, 20)]);
+        assert_eq!(SleepReport::new(&[]).sleepiest_guards(), Vec::<u64>::new());
     }
 }
@@ -1,54 +1,300 @@
 use std::io;
-use std::io::BufRead;
-use std::collections::BTreeSet;
-use failure::Error;
+use std::io::{BufRead, Read};
+use std::io::BufReader;
+use std::fs::File;
+use std::collections::{BTreeSet, HashMap};
+use disjoint_sets::UnionFind;
+use failure::{Error, ensure, format_err};
+use rayon::prelude::*;
 
-pub fn aoc5(part2: bool) -> Result<(), Error> {
-    let mut line = String::new();
-    io::stdin().lock().read_line(&mut line)?;
+/// Number of units each rayon task reacts at a time.
+const CHUNK_SIZE: usize = 1 << 16;
+
+/// Reacts the polymer on stdin, using the rules in `rules_path` if
+/// given, or the usual ones otherwise.
+pub fn aoc5(part2: bool, rules_path: Option<&str>) -> Result<(), Error> {
+    let rules = match rules_path {
+        Some(path) => ReactionRules::parse(&mut BufReader::new(File::open(path)?))?,
+        None => ReactionRules::default_rules(),
+    };
+    if rules.is_default() {
+        // The usual rules get the fast, streaming reactor.
+        let reacted = react_streaming(&mut io::stdin().lock(), CHUNK_SIZE)?;
+        if part2 {
+            println!("After reacting and removing most problematic: {}", remove_most_problematic(reacted).len());
+        } else {
+            println!("After reaction: {}", reacted.len());
+        }
+        return Ok(());
+    }
+    let mut polymer = String::new();
+    io::stdin().lock().read_to_string(&mut polymer)?;
+    let polymer: Vec<char> = polymer.chars().filter(|c| !c.is_whitespace()).collect();
     if part2 {
-        println!("After reacting and removing most problematic: {}", react_removing_most_problematic(&line.trim()).len());
+        let (class, reacted) = rules.react_removing_most_problematic(&polymer)
+            .ok_or_else(|| format_err!("No unit classes to remove"))?;
+        let class: String = class.iter().collect();
+        println!("After reacting and removing most problematic ({}): {}", class, reacted.len());
     } else {
-        println!("After reaction: {}", react(&line.trim()).len());
+        println!("After reaction: {}", rules.react(polymer).len());
     }
     Ok(())
 }
 
-fn react(polymer: &str) -> String {
-    let mut polymer_chars: Vec<char> = polymer.chars().collect();
-    let mut i = 0;
-    while i + 1 < polymer_chars.len() {
-        let prev = polymer_chars[i];
-        let next = polymer_chars[i + 1];
-        if prev.to_ascii_lowercase() == next.to_ascii_lowercase() &&
-           (prev.is_ascii_uppercase() && next.is_ascii_lowercase() ||
-            prev.is_ascii_lowercase() && next.is_ascii_uppercase()) {
-            // This is horribly inefficient
-            polymer_chars.remove(i);
-            polymer_chars.remove(i);
-            i = i.checked_sub(1).unwrap_or(0);
-        } else {
-            i += 1;
+/// Do these two units annihilate each other? (Same type, opposite polarity.)
+fn reacts(a: u8, b: u8) -> bool {
+    a != b && a.eq_ignore_ascii_case(&b)
+}
+
+/// Fully react a polymer, leaving out any units for which `skip`
+/// returns true.
+///
+/// Each unit either annihilates the last unit still standing or
+/// joins them, so a stack gives us the whole reaction in one pass.
+fn react_bytes(polymer: &[u8], skip: impl Fn(u8) -> bool) -> Vec<u8> {
+    let mut reacted = Vec::with_capacity(polymer.len());
+    for &unit in polymer {
+        if skip(unit) {
+            continue;
+        }
+        match reacted.last() {
+            Some(&last) if reacts(last, unit) => {
+                reacted.pop();
+            },
+            _ => reacted.push(unit),
+        }
+    }
+    reacted
+}
+
+/// Fully react a polymer with the usual rules.
+pub fn react(polymer: &str) -> String {
+    // Only pairs of ASCII letters ever react, so what's left is still UTF-8.
+    String::from_utf8(react_bytes(polymer.as_bytes(), |_| false)).unwrap()
+}
+
+/// Join two fully reacted polymers, reacting away any units that meet
+/// at the boundary. Since the reaction doesn't care what order pairs
+/// react in, this lets us react pieces of a polymer independently.
+fn merge_reacted(mut left: Vec<u8>, right: Vec<u8>) -> Vec<u8> {
+    let matched = left.iter().rev()
+        .zip(right.iter())
+        .take_while(|(&l, &r)| reacts(l, r))
+        .count();
+    left.truncate(left.len() - matched);
+    left.extend_from_slice(&right[matched..]);
+    left
+}
+
+/// Fully react a polymer by reacting chunks of it in parallel and
+/// merging the results. Units for which `skip` returns true are left
+/// out.
+fn react_parallel(polymer: &[u8], chunk_size: usize, skip: impl Fn(u8) -> bool + Sync) -> Vec<u8> {
+    polymer.par_chunks(chunk_size)
+        .map(|chunk| react_bytes(chunk, &skip))
+        .reduce(Vec::new, merge_reacted)
+}
+
+/// Fully react a polymer read from input (ignoring whitespace),
+/// holding only a batch of chunks of unreacted input in memory at a
+/// time.
+fn react_streaming(input: &mut impl BufRead, chunk_size: usize) -> Result<Vec<u8>, Error> {
+    let batch_size = chunk_size * rayon::current_num_threads();
+    let mut reacted = vec![];
+    let mut batch = Vec::with_capacity(batch_size);
+    loop {
+        batch.clear();
+        input.by_ref().take(batch_size as u64).read_to_end(&mut batch)?;
+        if batch.is_empty() {
+            return Ok(reacted);
+        }
+        let batch_reacted = react_parallel(&batch, chunk_size, |u| u.is_ascii_whitespace());
+        reacted = merge_reacted(reacted, batch_reacted);
+    }
+}
+
+/// Find the shortest polymer we can get by removing all units of a
+/// single type from an already-reacted polymer and reacting again.
+fn remove_most_problematic(reacted: Vec<u8>) -> Vec<u8> {
+    // Removing a unit type and then reacting gives the same result
+    // whether or not we react first, so start from the (usually much
+    // shorter) reacted polymer.
+    let units: BTreeSet<u8> = reacted.iter()
+        .filter(|u| u.is_ascii_alphabetic())
+        .map(|u| u.to_ascii_lowercase())
+        .collect();
+    units.into_par_iter()
+        .map(|unit_to_remove| react_bytes(&reacted, |u| u.to_ascii_lowercase() == unit_to_remove))
+        .min_by_key(|p| p.len())
+        .unwrap_or(reacted)
+}
+
+/// The shortest polymer left by removing one unit type and reacting,
+/// with the usual rules.
+pub fn react_removing_most_problematic(polymer: &str) -> String {
+    let reacted = react_bytes(polymer.as_bytes(), |_| false);
+    String::from_utf8(remove_most_problematic(reacted)).unwrap()
+}
+
+/// A table of which adjacent pairs of units react, and what they
+/// react into.
+#[derive(Debug, PartialEq)]
+struct ReactionRules {
+    /// What each (left, right) pair of units reacts into: None if
+    /// they annihilate, or the unit they transform into.
+    reactions: HashMap<(char, char), Option<char>>,
+    /// Groups of units that the removal search removes together.
+    classes: Vec<BTreeSet<char>>,
+}
+
+impl ReactionRules {
+    /// The usual rules: each ASCII letter annihilates the same letter
+    /// of the opposite case, and both cases form a class.
+    fn default_rules() -> Self {
+        let mut reactions = HashMap::new();
+        let mut classes = vec![];
+        for lower in b'a'..=b'z' {
+            let lower = lower as char;
+            let upper = lower.to_ascii_uppercase();
+            reactions.insert((lower, upper), None);
+            reactions.insert((upper, lower), None);
+            classes.push(vec![lower, upper].into_iter().collect());
+        }
+        ReactionRules { reactions, classes }
+    }
+
+    /// Whether these are the usual rules, whatever order the classes
+    /// are listed in.
+    fn is_default(&self) -> bool {
+        let default = Self::default_rules();
+        let classes: BTreeSet<&BTreeSet<char>> = self.classes.iter().collect();
+        self.reactions == default.reactions && classes == default.classes.iter().collect()
+    }
+
+    /// Parse rules from lines like:
+    ///
+    /// ```text
+    /// # Comment
+    /// a A ->
+    /// α β -> γ
+    /// class a A
+    /// ```
+    ///
+    /// The first line says "a followed by A annihilate", the second
+    /// says "α followed by β become γ", and the last declares a class
+    /// for the removal search. If no classes are declared, units that
+    /// annihilate each other are grouped into classes.
+    fn parse(input: &mut impl BufRead) -> Result<Self, Error> {
+        let mut reactions = HashMap::new();
+        let mut classes = vec![];
+        for (i, line_result) in input.lines().enumerate() {
+            let line = line_result?;
+            let line_number = i + 1;
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            if tokens.is_empty() || tokens[0].starts_with('#') {
+                continue;
+            }
+            if tokens[0] == "class" {
+                let class = tokens[1..].iter()
+                    .map(|t| parse_unit(t, line_number))
+                    .collect::<Result<BTreeSet<char>, Error>>()?;
+                ensure!(!class.is_empty(), "Empty class on line {}", line_number);
+                classes.push(class);
+                continue;
+            }
+            ensure!((tokens.len() == 3 || tokens.len() == 4) && tokens[2] == "->",
+                    "Line {} isn't a rule like \"a A ->\" or \"a b -> c\": {}", line_number, line);
+            let left = parse_unit(tokens[0], line_number)?;
+            let right = parse_unit(tokens[1], line_number)?;
+            let product = match tokens.get(3) {
+                Some(token) => Some(parse_unit(token, line_number)?),
+                None => None,
+            };
+            ensure!(reactions.insert((left, right), product).is_none(),
+                    "Duplicate rule for {}{} on line {}", left, right, line_number);
+        }
+        if classes.is_empty() {
+            classes = annihilation_classes(&reactions);
+        }
+        Ok(ReactionRules { reactions, classes })
+    }
+
+    /// Fully react a polymer.
+    ///
+    /// When a new unit arrives it reacts with the last unit still
+    /// standing, and anything it transforms into keeps reacting
+    /// leftwards. With rules that only annihilate, the order doesn't
+    /// matter, but with transformations this is "leftmost pair
+    /// first".
+    fn react(&self, polymer: impl IntoIterator<Item = char>) -> Vec<char> {
+        let mut reacted: Vec<char> = vec![];
+        for mut unit in polymer {
+            loop {
+                match reacted.last().and_then(|&last| self.reactions.get(&(last, unit))) {
+                    None => {
+                        reacted.push(unit);
+                        break;
+                    },
+                    Some(None) => {
+                        reacted.pop();
+                        break;
+                    },
+                    Some(Some(product)) => {
+                        unit = *product;
+                        reacted.pop();
+                    },
+                }
+            }
         }
+        reacted
+    }
+
+    /// Find the class whose removal leaves the shortest polymer after
+    /// reacting, along with that polymer. None if there are no classes.
+    fn react_removing_most_problematic(&self, polymer: &[char]) -> Option<(&BTreeSet<char>, Vec<char>)> {
+        self.classes.par_iter()
+            .map(|class| (class, self.react(polymer.iter().cloned().filter(|u| !class.contains(u)))))
+            .min_by_key(|(_, reacted)| reacted.len())
     }
-    polymer_chars.into_iter().collect()
 }
 
-fn react_removing_most_problematic(polymer: &str) -> String {
-    let new_polymer = react(polymer);
-    let chars: BTreeSet<char> = new_polymer.chars().map(|c| c.to_ascii_lowercase()).collect();
-    let less_problematic_polymers: Vec<String> = chars.iter()
-        .map(|char_to_remove| {
-            new_polymer.chars().filter(|c| c.to_ascii_lowercase() != *char_to_remove).collect::<String>()
-        })
-        .map(|s| react(&s))
+/// Parse a single unit, which must be exactly one character.
+fn parse_unit(token: &str, line_number: usize) -> Result<char, Error> {
+    let mut chars = token.chars();
+    match (chars.next(), chars.next()) {
+        (Some(unit), None) => Ok(unit),
+        _ => Err(format_err!("Unit {} on line {} isn't a single character", token, line_number)),
+    }
+}
+
+/// Group all the units mentioned in the reactions into classes of
+/// units that annihilate each other.
+fn annihilation_classes(reactions: &HashMap<(char, char), Option<char>>) -> Vec<BTreeSet<char>> {
+    let units: Vec<char> = reactions.iter()
+        .flat_map(|(&(left, right), product)| vec![Some(left), Some(right), *product])
+        .flatten()
+        .collect::<BTreeSet<char>>()
+        .into_iter()
         .collect();
-    less_problematic_polymers.iter().min_by_key(|s| s.len()).unwrap().to_string()
+    let index: HashMap<char, usize> = units.iter().enumerate().map(|(i, &u)| (u, i)).collect();
+    let mut unionfind = UnionFind::new(units.len());
+    for (&(left, right), product) in reactions {
+        if product.is_none() {
+            unionfind.union(index[&left], index[&right]);
+        }
+    }
+    let mut classes: Vec<BTreeSet<char>> = vec![BTreeSet::new(); units.len()];
+    for (i, &unit) in units.iter().enumerate() {
+        classes[unionfind.find(i)].insert(unit);
+    }
+    classes.into_iter().filter(|c| !c.is_empty()).collect()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rand::Rng;
+    use rand::seq::SliceRandom;
 
     #[test]
     fn test_react() {
@@ -63,4 +309,82 @@ mod tests {
     fn test_react_removing_most_problematic() {
         assert_eq!(react_removing_most_problematic("dabAcCaCBAcCcaDA"), "daDA");
     }
+
+    #[test]
+    fn test_react_large_polymer() {
+        let mut polymer = "dabAcCaCBAcCcaDA".to_string();
+        for _ in 0..1_000_000 {
+            polymer.push_str("xyYX");
+        }
+        polymer.push_str("Éé");
+        assert_eq!(react(&polymer), "dabCBAcaDAÉé");
+        assert_eq!(react_removing_most_problematic(&polymer), "daDAÉé");
+        assert_eq!(react_removing_most_problematic("aA"), "");
+    }
+
+    #[test]
+    fn test_react_in_chunks() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..200 {
+            let len = rng.gen_range(0, 200);
+            let polymer: String = (0..len).map(|_| *b"aAbBc".choose(&mut rng).unwrap() as char).collect();
+            let expected = react(&polymer);
+            let chunk_size = rng.gen_range(1, 10);
+            assert_eq!(react_parallel(polymer.as_bytes(), chunk_size, |_| false), expected.as_bytes());
+            let input = format!("{}\n", polymer);
+            let reacted = react_streaming(&mut input.as_bytes(), chunk_size).unwrap();
+            assert_eq!(reacted, expected.as_bytes());
+        }
+    }
+
+    #[test]
+    fn test_default_rules() {
+        let rules = ReactionRules::default_rules();
+        let polymer: Vec<char> = "dabAcCaCBAcCcaDA".chars().collect();
+        assert_eq!(rules.react(polymer.clone()).into_iter().collect::<String>(), "dabCBAcaDA");
+        let (class, reacted) = rules.react_removing_most_problematic(&polymer).unwrap();
+        assert_eq!(class.iter().collect::<String>(), "Cc");
+        assert_eq!(reacted.into_iter().collect::<String>(), "daDA");
+        assert!(rules.is_default());
+
+        // Spelling out the usual rules in a file gives the same rules.
+        let spelled_out: String = (b'a'..=b'z')
+            .map(|u| format!("{0} {1} ->\n{1} {0} ->\n", u as char, u.to_ascii_uppercase() as char))
+            .collect();
+        assert!(ReactionRules::parse(&mut spelled_out.as_bytes()).unwrap().is_default());
+        assert!(!ReactionRules::parse(&mut "a A ->\nA a ->\n".as_bytes()).unwrap().is_default());
+    }
+
+    #[test]
+    fn test_parse_rules() {
+        let rules = ReactionRules::parse(&mut "# Greek chemistry
+α Α ->
+Α α ->
+
+β β -> γ
+α γ -> δ
+".as_bytes()).unwrap();
+        assert_eq!(rules.reactions.len(), 4);
+        assert_eq!(rules.reactions[&('β', 'β')], Some('γ'));
+        assert_eq!(rules.reactions[&('α', 'γ')], Some('δ'));
+        assert_eq!(rules.classes, vec![
+            vec!['Α', 'α'].into_iter().collect(),
+            vec!['β'].into_iter().collect(),
+            vec!['γ'].into_iter().collect(),
+            vec!['δ'].into_iter().collect(),
+        ]);
+        // ββ becomes γ, which then reacts with the α before it to become δ.
+        assert_eq!(rules.react("αββαΑε".chars()).into_iter().collect::<String>(), "δε");
+        let polymer: Vec<char> = "αββαΑββ".chars().collect();
+        let (class, reacted) = rules.react_removing_most_problematic(&polymer).unwrap();
+        assert_eq!(class.iter().collect::<String>(), "β");
+        assert_eq!(reacted, vec!['α']);
+
+        let rules = ReactionRules::parse(&mut "a A ->\nclass a b\n".as_bytes()).unwrap();
+        assert_eq!(rules.classes, vec![vec!['a', 'b'].into_iter().collect()]);
+
+        assert!(ReactionRules::parse(&mut "a A".as_bytes()).is_err());
+        assert!(ReactionRules::parse(&mut "ab A ->".as_bytes()).is_err());
+        assert!(ReactionRules::parse(&mut "a A ->\na A -> b".as_bytes()).is_err());
+    }
 }
@@ -1,14 +1,13 @@
 use std::io;
 use std::io::BufRead;
-use std::collections::{HashSet, HashMap};
 use failure::{Error, format_err};
 
 pub fn aoc6(part2: bool) -> Result<(), Error> {
     let coords = parse_coords(&mut io::stdin().lock())?;
     if part2 {
-        println!("Size of close region: {}", size_of_close_region(&coords, 10000).expect("Couldn't find close region"));
+        println!("Size of close region: {}", size_of_close_region(&coords, 10000, Metric::Manhattan).expect("Couldn't find close region"));
     } else {
-        println!("Largest non-infinite area: {}", largest_non_infinite_area(&coords).expect("Couldn't find area"));
+        println!("Largest non-infinite area: {}", largest_non_infinite_area(&coords, Metric::Manhattan).expect("Couldn't find area"));
     }
     Ok(())
 }
@@ -19,12 +18,6 @@ pub struct Coord {
     pub y: i64,
 }
 
-impl Coord {
-    fn distance(&self, other: &Coord) -> i64 {
-        (other.x - self.x).abs() + (other.y - self.y).abs()
-    }
-}
-
 fn parse_coord(string: &str) -> Result<Coord, Error> {
     let mut iter = string.split(", ").map(|c| c.parse::<i64>());
     let x = iter.next().ok_or_else(|| format_err!("not enough fields"))??;
@@ -46,45 +39,542 @@ fn get_bounding_box(coords: &[Coord]) -> Option<BBox> {
     Some((min_x, max_x, min_y, max_y))
 }
 
-fn largest_non_infinite_area(coords: &[Coord]) -> Option<u64> {
-    let bbox = get_bounding_box(&coords)?;
-    let width = bbox.1 - bbox.0;
-    let height = bbox.3 - bbox.2;
-    let mut assignments: Vec<Option<&Coord>> = vec![None; (width * height) as usize];
-    let mut infinite_coords: HashSet<&Coord> = HashSet::new();
-    for x in bbox.0..=bbox.1 {
-        for y in bbox.2..=bbox.3 {
-            let on_edge = x == bbox.0 || x == bbox.1 || y == bbox.2 || y == bbox.3;
+/// How to measure the distance between two points.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Metric {
+    Manhattan,
+    Chebyshev,
+    SquaredEuclidean,
+    /// Manhattan distance with each axis scaled by a weight, which
+    /// must be at least 1. Region functions return `None` otherwise.
+    WeightedManhattan { x_weight: i64, y_weight: i64 },
+}
+
+impl Metric {
+    /// Whether the metric's parameters make sense.
+    pub fn is_valid(&self) -> bool {
+        match *self {
+            Metric::WeightedManhattan { x_weight, y_weight } => x_weight >= 1 && y_weight >= 1,
+            Metric::Manhattan | Metric::Chebyshev | Metric::SquaredEuclidean => true,
+        }
+    }
+
+    pub fn distance(&self, a: &Coord, b: &Coord) -> i64 {
+        let dx = (a.x - b.x).abs();
+        let dy = (a.y - b.y).abs();
+        match *self {
+            Metric::Manhattan => dx + dy,
+            Metric::Chebyshev => dx.max(dy),
+            Metric::SquaredEuclidean => dx * dx + dy * dy,
+            Metric::WeightedManhattan { x_weight, y_weight } => x_weight * dx + y_weight * dy,
+        }
+    }
+
+    /// For metrics that are a weighted sum of the x and y distances,
+    /// the weight of each.
+    fn axis_weights(&self) -> Option<(i64, i64)> {
+        match *self {
+            Metric::Manhattan => Some((1, 1)),
+            Metric::WeightedManhattan { x_weight, y_weight } => Some((x_weight, y_weight)),
+            Metric::Chebyshev | Metric::SquaredEuclidean => None,
+        }
+    }
+
+    /// For metrics that are shortest-path distances on the grid, the
+    /// steps allowed from a cell and what each one costs.
+    fn grid_steps(&self) -> Option<Vec<(i64, i64, i64)>> {
+        match *self {
+            Metric::Manhattan => Some(vec![(-1, 0, 1), (1, 0, 1), (0, -1, 1), (0, 1, 1)]),
+            Metric::WeightedManhattan { x_weight, y_weight } =>
+                Some(vec![(-1, 0, x_weight), (1, 0, x_weight), (0, -1, y_weight), (0, 1, y_weight)]),
+            Metric::Chebyshev => Some(vec![(-1, -1, 1), (-1, 0, 1), (-1, 1, 1), (0, -1, 1),
+                                           (0, 1, 1), (1, -1, 1), (1, 0, 1), (1, 1, 1)]),
+            Metric::SquaredEuclidean => None,
+        }
+    }
+
+    /// How far to pad the sites' bounding box so that the map contains
+    /// every finite region, and so that (for path metrics) a region is
+    /// infinite exactly when it reaches the edge of the map.
+    ///
+    /// For the Manhattan metrics, stepping straight away from the
+    /// bounding box adds the same amount to every site's distance, so
+    /// one cell is enough. Chebyshev distance only behaves that way
+    /// once we're further out than the box is wide or tall.
+    fn padding(&self, coords: &[Coord], bbox: BBox) -> i64 {
+        match *self {
+            Metric::Manhattan | Metric::WeightedManhattan { .. } => 1,
+            Metric::Chebyshev => (bbox.1 - bbox.0).max(bbox.3 - bbox.2) + 1,
+            Metric::SquaredEuclidean => euclidean_padding(coords, bbox),
+        }
+    }
+}
+
+/// Is point c to the left of (positive), on (zero), or to the right of
+/// (negative) the line from a to b?
+fn cross(a: &Coord, b: &Coord, c: &Coord) -> i64 {
+    (b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x)
+}
+
+/// Vertices of the convex hull, counter-clockwise, without collinear
+/// points.
+fn convex_hull(coords: &[Coord]) -> Vec<Coord> {
+    let mut points = coords.to_vec();
+    points.sort_by_key(|c| (c.x, c.y));
+    points.dedup();
+    if points.len() < 3 {
+        return points;
+    }
+    let mut hull: Vec<Coord> = vec![];
+    for pass in 0..2 {
+        let start = hull.len();
+        for point in &points {
+            while hull.len() >= start + 2 && cross(&hull[hull.len() - 2], &hull[hull.len() - 1], point) <= 0 {
+                hull.pop();
+            }
+            hull.push(*point);
+        }
+        hull.pop();
+        if pass == 0 {
+            points.reverse();
+        }
+    }
+    hull
+}
+
+/// Hull edges, as pairs of consecutive vertices.
+fn hull_edges(hull: &[Coord]) -> impl Iterator<Item = (&Coord, &Coord)> {
+    hull.iter().zip(hull.iter().cycle().skip(1))
+}
+
+/// Is the site on the boundary of the convex hull (including in the
+/// middle of an edge)? A site's Euclidean region is infinite exactly
+/// when it is: past the hull there's a direction in which it's the
+/// furthest site out, and far enough that way it's the closest.
+fn on_hull_boundary(hull: &[Coord], site: &Coord) -> bool {
+    if hull.len() < 3 {
+        // All the sites are in a line, so they're all on the boundary.
+        return true;
+    }
+    hull_edges(hull).any(|(a, b)| {
+        cross(a, b, site) == 0
+            && site.x >= a.x.min(b.x) && site.x <= a.x.max(b.x)
+            && site.y >= a.y.min(b.y) && site.y <= a.y.max(b.y)
+    })
+}
+
+/// Padding needed to contain every finite Euclidean region.
+fn euclidean_padding(coords: &[Coord], bbox: BBox) -> i64 {
+    let hull = convex_hull(coords);
+    let mut padding: f64 = 0.0;
+    for (site, coord) in coords.iter().enumerate() {
+        if on_hull_boundary(&hull, coord) {
+            continue;
+        }
+        for (x, y) in euclidean_cell(coords, site, &hull, bbox) {
+            padding = padding
+                .max(bbox.0 as f64 - x).max(x - bbox.1 as f64)
+                .max(bbox.2 as f64 - y).max(y - bbox.3 as f64);
+        }
+    }
+    padding.ceil() as i64 + 1
+}
+
+/// Corners of the (bounded) Euclidean region of a site inside the hull.
+///
+/// We start from a square that is sure to contain the region and cut
+/// away the half of the plane closer to each other site. The square
+/// comes from the fact that if the site is a distance d inside the
+/// hull, then any point further than D^2 / 2d from it (where D is the
+/// bounding box's diagonal) is closer to whichever site lies furthest
+/// in that point's direction.
+fn euclidean_cell(coords: &[Coord], site: usize, hull: &[Coord], bbox: BBox) -> Vec<(f64, f64)> {
+    let s = coords[site];
+    let depth = hull_edges(hull)
+        .map(|(a, b)| cross(a, b, &s) as f64 / (((b.x - a.x).pow(2) + (b.y - a.y).pow(2)) as f64).sqrt())
+        .fold(f64::INFINITY, f64::min);
+    let diagonal_squared = ((bbox.1 - bbox.0).pow(2) + (bbox.3 - bbox.2).pow(2)) as f64;
+    let reach = diagonal_squared / (2.0 * depth) + 1.0;
+    let (sx, sy) = (s.x as f64, s.y as f64);
+    let mut cell = vec![(sx - reach, sy - reach), (sx + reach, sy - reach),
+                        (sx + reach, sy + reach), (sx - reach, sy + reach)];
+    for other in coords.iter().filter(|c| **c != s) {
+        // Points p no further from s than from other satisfy a . p <= c.
+        let a = (2.0 * (other.x - s.x) as f64, 2.0 * (other.y - s.y) as f64);
+        let c = ((other.x.pow(2) + other.y.pow(2)) - (s.x.pow(2) + s.y.pow(2))) as f64;
+        let inside = |p: &(f64, f64)| a.0 * p.0 + a.1 * p.1 <= c;
+        let mut clipped = vec![];
+        for (i, p) in cell.iter().enumerate() {
+            let q = &cell[(i + 1) % cell.len()];
+            if inside(p) {
+                clipped.push(*p);
+            }
+            if inside(p) != inside(q) {
+                let fp = a.0 * p.0 + a.1 * p.1 - c;
+                let fq = a.0 * q.0 + a.1 * q.1 - c;
+                let t = fp / (fp - fq);
+                clipped.push((p.0 + t * (q.0 - p.0), p.1 + t * (q.1 - p.1)));
+            }
+        }
+        cell = clipped;
+    }
+    cell
+}
+
+/// Which site a cell is closest to.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Owner {
+    /// Index of the unique nearest site.
+    Site(usize),
+    /// Two or more sites are equally close.
+    Tie,
+}
+
+/// The region of the plane closest to each site, within a padded
+/// bounding box that is big enough to hold every finite region.
+#[derive(Debug)]
+pub struct RegionMap {
+    /// The padded bounding box covered by the map.
+    pub bbox: BBox,
+    /// Owner of every cell in the padded bounding box, row by row.
+    pub owners: Vec<Owner>,
+    /// Number of cells in the map owned by each site.
+    pub areas: Vec<u64>,
+    /// Whether each site's region extends infinitely far.
+    pub infinite: Vec<bool>,
+}
+
+impl RegionMap {
+    pub fn new(coords: &[Coord], metric: Metric) -> Option<Self> {
+        if !metric.is_valid() {
+            return None;
+        }
+        let bbox = get_bounding_box(coords)?;
+        let padding = metric.padding(coords, bbox);
+        let bbox = (bbox.0 - padding, bbox.1 + padding, bbox.2 - padding, bbox.3 + padding);
+        let width = (bbox.1 - bbox.0 + 1) as usize;
+        let height = (bbox.3 - bbox.2 + 1) as usize;
+        let owners = match metric.grid_steps() {
+            Some(steps) => flood_fill(coords, bbox, &steps),
+            None => nearest_sites(coords, bbox, metric),
+        };
+
+        let mut areas = vec![0; coords.len()];
+        let mut infinite = vec![false; coords.len()];
+        for (index, owner) in owners.iter().enumerate() {
+            if let Owner::Site(site) = *owner {
+                areas[site] += 1;
+                let (x, y) = (index % width, index / width);
+                if x == 0 || x == width - 1 || y == 0 || y == height - 1 {
+                    infinite[site] = true;
+                }
+            }
+        }
+        if metric == Metric::SquaredEuclidean {
+            // The edge of the map says nothing here, but the hull does.
+            let hull = convex_hull(coords);
+            for (site, coord) in coords.iter().enumerate() {
+                let duplicated = coords.iter().filter(|c| *c == coord).count() > 1;
+                infinite[site] = !duplicated && on_hull_boundary(&hull, coord);
+            }
+        }
+        Some(RegionMap { bbox, owners, areas, infinite })
+    }
+
+    /// Which site owns the given point, or None if it's outside the map.
+    pub fn owner(&self, point: Coord) -> Option<Owner> {
+        if point.x < self.bbox.0 || point.x > self.bbox.1 || point.y < self.bbox.2 || point.y > self.bbox.3 {
+            return None;
+        }
+        let width = self.bbox.1 - self.bbox.0 + 1;
+        Some(self.owners[((point.y - self.bbox.2) * width + point.x - self.bbox.0) as usize])
+    }
+
+    /// Area of each site with a finite region, as (site index, area).
+    pub fn finite_areas(&self) -> impl Iterator<Item = (usize, u64)> + '_ {
+        self.areas.iter()
+            .cloned()
+            .enumerate()
+            .filter(move |&(site, _)| !self.infinite[site])
+    }
+}
+
+/// Label every cell in the box with its nearest site, for metrics
+/// that are shortest paths over the given steps, using a flood fill
+/// (Dijkstra's algorithm) starting from all the sites at once.
+///
+/// Every site that is nearest to a cell is also nearest to the
+/// neighbor that a shortest path to the cell passes through, so each
+/// cell's owner follows from the owners of the cells that reached it
+/// first.
+fn flood_fill(coords: &[Coord], bbox: BBox, steps: &[(i64, i64, i64)]) -> Vec<Owner> {
+    let width = (bbox.1 - bbox.0 + 1) as usize;
+    let height = (bbox.3 - bbox.2 + 1) as usize;
+    let mut owners: Vec<Option<Owner>> = vec![None; width * height];
+    let mut distances: Vec<i64> = vec![i64::MAX; width * height];
+    // Step costs are small integers, so a queue with one bucket per
+    // distance is much quicker than a heap.
+    let mut buckets: Vec<Vec<usize>> = vec![vec![]];
+    for (site, coord) in coords.iter().enumerate() {
+        let index = (coord.y - bbox.2) as usize * width + (coord.x - bbox.0) as usize;
+        owners[index] = match owners[index] {
+            None => {
+                distances[index] = 0;
+                buckets[0].push(index);
+                Some(Owner::Site(site))
+            },
+            // Two sites in the same place.
+            Some(_) => Some(Owner::Tie),
+        };
+    }
+    let mut distance = 0;
+    while distance < buckets.len() {
+        let bucket = std::mem::take(&mut buckets[distance]);
+        for index in bucket {
+            if distances[index] != distance as i64 {
+                // Already reached more cheaply.
+                continue;
+            }
+            let (x, y) = ((index % width) as i64, (index / width) as i64);
+            let owner = owners[index];
+            for &(dx, dy, cost) in steps {
+                let (nx, ny) = (x + dx, y + dy);
+                if nx < 0 || ny < 0 || nx >= width as i64 || ny >= height as i64 {
+                    continue;
+                }
+                let neighbor = ny as usize * width + nx as usize;
+                let new_distance = distance as i64 + cost;
+                if new_distance < distances[neighbor] {
+                    distances[neighbor] = new_distance;
+                    owners[neighbor] = owner;
+                    if buckets.len() <= new_distance as usize {
+                        buckets.resize(new_distance as usize + 1, vec![]);
+                    }
+                    buckets[new_distance as usize].push(neighbor);
+                } else if new_distance == distances[neighbor] && owners[neighbor] != owner {
+                    owners[neighbor] = Some(Owner::Tie);
+                }
+            }
+        }
+        distance += 1;
+    }
+    owners.into_iter().map(|o| o.unwrap()).collect()
+}
+
+/// Label every cell in the box with its nearest site by checking
+/// every site.
+fn nearest_sites(coords: &[Coord], bbox: BBox, metric: Metric) -> Vec<Owner> {
+    let mut owners = vec![];
+    for y in bbox.2..=bbox.3 {
+        for x in bbox.0..=bbox.1 {
             let point = Coord { x, y };
-            let min_coord = coords.iter().min_by_key(|c| point.distance(c)).unwrap();
-            let unique = coords.iter().filter(|coord| point.distance(coord) == point.distance(min_coord)).count() == 1;
-            if on_edge {
-                infinite_coords.insert(min_coord);
-            } else if unique {
-                let index: usize = (width * (y - bbox.2) + (x - bbox.0)) as usize;
-                assert!(assignments[index] == None);
-                assignments[index] = Some(min_coord);
+            let mut best = (i64::MAX, Owner::Tie);
+            for (site, coord) in coords.iter().enumerate() {
+                let distance = metric.distance(&point, coord);
+                if distance < best.0 {
+                    best = (distance, Owner::Site(site));
+                } else if distance == best.0 {
+                    best.1 = Owner::Tie;
+                }
             }
+            owners.push(best.1);
         }
     }
-    let mut counts: HashMap<&Coord, u64> = HashMap::new();
-    for assignment in assignments {
-        if let Some(coord) = assignment {
-             if !infinite_coords.contains(coord) {
-                 *counts.entry(coord).or_insert(0) += 1;
-             }
+    owners
+}
+
+pub fn largest_non_infinite_area(coords: &[Coord], metric: Metric) -> Option<u64> {
+    RegionMap::new(coords, metric)?.finite_areas().map(|(_, area)| area).max()
+}
+
+/// The summed (weighted) distance along one axis from every integer
+/// position to a set of sites.
+///
+/// This is convex, and outside the sites' range it just grows by the
+/// same amount (slope) with every step, so we only store the values
+/// between the first and last site.
+struct DistanceProfile {
+    /// Smallest site coordinate.
+    start: i64,
+    /// Summed distance at each position from the smallest site
+    /// coordinate to the largest.
+    inner: Vec<i64>,
+    /// How much the summed distance grows with each step outside that
+    /// range.
+    slope: i64,
+}
+
+impl DistanceProfile {
+    fn new(positions: impl Iterator<Item = i64>, weight: i64) -> Self {
+        let mut positions: Vec<i64> = positions.collect();
+        positions.sort();
+        let start = positions[0];
+        let end = positions[positions.len() - 1];
+        let mut inner = Vec::with_capacity((end - start + 1) as usize);
+        let mut sum: i64 = positions.iter().map(|p| weight * (p - start)).sum();
+        // Number of sites at or before the current position.
+        let mut passed = 0;
+        for pos in start..=end {
+            while passed < positions.len() && positions[passed] <= pos {
+                passed += 1;
+            }
+            inner.push(sum);
+            sum += weight * (2 * passed as i64 - positions.len() as i64);
+        }
+        DistanceProfile { start, inner, slope: weight * positions.len() as i64 }
+    }
+
+    fn end(&self) -> i64 {
+        self.start + self.inner.len() as i64 - 1
+    }
+
+    /// The summed distance at any position.
+    fn at(&self, pos: i64) -> i64 {
+        if pos < self.start {
+            self.inner[0] + self.slope * (self.start - pos)
+        } else if pos > self.end() {
+            self.inner[self.inner.len() - 1] + self.slope * (pos - self.end())
+        } else {
+            self.inner[(pos - self.start) as usize]
+        }
+    }
+
+    /// The (inclusive) range of positions with summed distance below
+    /// limit, or None if there aren't any.
+    fn positions_below(&self, limit: i64) -> Option<(i64, i64)> {
+        let min_index = (0..self.inner.len()).min_by_key(|&i| self.inner[i])?;
+        if self.inner[min_index] >= limit {
+            return None;
+        }
+        // The profile decreases up to its minimum and increases after.
+        let first = if self.inner[0] < limit {
+            self.start - (limit - 1 - self.inner[0]) / self.slope
+        } else {
+            self.start + self.inner[..min_index].partition_point(|&d| d >= limit) as i64
+        };
+        let last = if self.inner[self.inner.len() - 1] < limit {
+            self.end() + (limit - 1 - self.inner[self.inner.len() - 1]) / self.slope
+        } else {
+            self.start + min_index as i64 + self.inner[min_index..].partition_point(|&d| d < limit) as i64 - 1
+        };
+        Some((first, last))
+    }
+}
+
+/// Number of steps k >= 1 outside the sites' range for which
+/// base + slope * k < limit.
+fn steps_below(base: i64, slope: i64, limit: i64) -> u64 {
+    if base + slope >= limit {
+        0
+    } else {
+        ((limit - 1 - base) / slope) as u64
+    }
+}
+
+/// Sum of floor((a * i + b) / m) for i in 0..n, for non-negative a
+/// and b, in O(log m) steps.
+fn floor_sum(mut n: i128, mut m: i128, mut a: i128, mut b: i128) -> i128 {
+    let mut sum = 0;
+    loop {
+        if a >= m {
+            sum += n * (n - 1) / 2 * (a / m);
+            a %= m;
+        }
+        if b >= m {
+            sum += n * (b / m);
+            b %= m;
+        }
+        let y_max = a * n + b;
+        if y_max < m {
+            return sum;
+        }
+        n = y_max / m;
+        b = y_max % m;
+        std::mem::swap(&mut m, &mut a);
+    }
+}
+
+/// Count the positions (x, y) where xs.at(x) + ys.at(y) < limit,
+/// without visiting them.
+///
+/// Each profile is its stored inner values plus two arms where it
+/// grows by a constant slope, so we count inner/inner pairs by
+/// walking both sorted inner lists together, inner/arm pairs with a
+/// division each, and arm/arm pairs with a floor sum.
+fn count_positions_below(xs: &DistanceProfile, ys: &DistanceProfile, limit: i64) -> u64 {
+    let mut inner_x = xs.inner.clone();
+    inner_x.sort();
+    let mut inner_y = ys.inner.clone();
+    inner_y.sort();
+    let x_arms = [xs.inner[0], xs.inner[xs.inner.len() - 1]];
+    let y_arms = [ys.inner[0], ys.inner[ys.inner.len() - 1]];
+    let mut count = 0;
+
+    // Inner x, inner y.
+    let mut num_y = inner_y.len();
+    for &u in &inner_x {
+        while num_y > 0 && u + inner_y[num_y - 1] >= limit {
+            num_y -= 1;
         }
+        count += num_y as u64;
+    }
+    // Inner x, arms of y (and vice versa).
+    for &arm in &y_arms {
+        count += inner_x.iter().map(|&u| steps_below(u + arm, ys.slope, limit)).sum::<u64>();
+    }
+    for &arm in &x_arms {
+        count += inner_y.iter().map(|&w| steps_below(w + arm, xs.slope, limit)).sum::<u64>();
+    }
+    // Arms of both. For each step k out along the x arm, the number
+    // of y steps that fit is a floor of a linear function of k, so
+    // the total is a floor sum.
+    for &x_arm in &x_arms {
+        for &y_arm in &y_arms {
+            let max_x_steps = steps_below(x_arm + y_arm + ys.slope, xs.slope, limit);
+            if max_x_steps > 0 {
+                // Summing from the last x step backwards keeps everything non-negative.
+                let last_room = limit - 1 - x_arm - y_arm - xs.slope * max_x_steps as i64;
+                count += floor_sum(i128::from(max_x_steps), i128::from(ys.slope),
+                                   i128::from(xs.slope), i128::from(last_room)) as u64;
+            }
+        }
+    }
+    count
+}
+
+/// Profiles along each axis, for metrics where that makes sense.
+fn axis_profiles(coords: &[Coord], metric: Metric) -> Option<(DistanceProfile, DistanceProfile)> {
+    let (x_weight, y_weight) = metric.axis_weights()?;
+    if coords.is_empty() {
+        return None;
     }
-    Some(*counts.values().max()?)
+    Some((DistanceProfile::new(coords.iter().map(|c| c.x), x_weight),
+          DistanceProfile::new(coords.iter().map(|c| c.y), y_weight)))
 }
 
-fn size_of_close_region(coords: &[Coord], close_distance: i64) -> Option<u64> {
+/// Number of cells whose total distance to all the sites is less than
+/// close_distance.
+///
+/// For the Manhattan metrics, this is exact and doesn't depend on how
+/// big close_distance is. For others, we check every cell that could
+/// possibly be in the region, which can be slow.
+pub fn size_of_close_region(coords: &[Coord], close_distance: i64, metric: Metric) -> Option<u64> {
+    if !metric.is_valid() {
+        return None;
+    }
     let bbox = get_bounding_box(&coords)?;
+    if let Some((xs, ys)) = axis_profiles(coords, metric) {
+        return Some(count_positions_below(&xs, &ys, close_distance));
+    }
+    // Every other metric puts each site at least as far away as the
+    // Chebyshev distance to the bounding box, so nothing further than
+    // this can be in the region.
+    let padding = close_distance / coords.len() as i64;
     let mut area = 0;
-    for x in bbox.0..=bbox.1 {
-        for y in bbox.2..=bbox.3 {
+    for x in bbox.0 - padding..=bbox.1 + padding {
+        for y in bbox.2 - padding..=bbox.3 + padding {
             let point = Coord { x, y };
-            let distance_sum: i64 = coords.iter().map(|c| point.distance(c)).sum();
+            let distance_sum: i64 = coords.iter().map(|c| metric.distance(&point, c)).sum();
             if distance_sum < close_distance {
                 area += 1;
             }
@@ -93,10 +583,30 @@ fn size_of_close_region(coords: &[Coord], close_distance: i64) -> Option<u64> {
     Some(area)
 }
 
+/// The outline of the close region, as the (inclusive) range of y
+/// covered in each column: (x, min y, max y). Only available for the
+/// Manhattan metrics, and takes time proportional to the width of the
+/// region.
+pub fn close_region_outline(coords: &[Coord], close_distance: i64, metric: Metric) -> Option<Vec<(i64, i64, i64)>> {
+    if !metric.is_valid() {
+        return None;
+    }
+    let (xs, ys) = axis_profiles(coords, metric)?;
+    let min_y_distance = *ys.inner.iter().min()?;
+    let (first_x, last_x) = match xs.positions_below(close_distance - min_y_distance) {
+        Some(range) => range,
+        None => return Some(vec![]),
+    };
+    Some((first_x..=last_x)
+         .filter_map(|x| ys.positions_below(close_distance - xs.at(x)).map(|(y1, y2)| (x, y1, y2)))
+         .collect())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::fmt::Debug;
+    use rand::Rng;
 
     /// This function allows us to assert that a Result is
     /// Ok(expected) without requiring PartialEq on the Error type.
@@ -136,12 +646,173 @@ mod tests {
     #[test]
     fn test_largest_non_infinite_area() {
         let coords = parse_coords(&mut COORDS.as_bytes()).expect("Couldn't parse coordinates");
-        assert_eq!(largest_non_infinite_area(&coords), Some(17))
+        assert_eq!(largest_non_infinite_area(&coords, Metric::Manhattan), Some(17))
     }
 
     #[test]
     fn test_size_of_close_region() {
         let coords = parse_coords(&mut COORDS.as_bytes()).expect("Couldn't parse coordinates");
-        assert_eq!(size_of_close_region(&coords, 32), Some(16))
+        assert_eq!(size_of_close_region(&coords, 32, Metric::Manhattan), Some(16))
+    }
+
+    #[test]
+    fn test_region_map() {
+        let coords = parse_coords(&mut COORDS.as_bytes()).expect("Couldn't parse coordinates");
+        let regions = RegionMap::new(&coords, Metric::Manhattan).unwrap();
+        assert_eq!(regions.bbox, (0, 9, 0, 10));
+        assert_eq!(regions.areas[3], 9);
+        assert_eq!(regions.areas[4], 17);
+        assert_eq!(regions.infinite, vec![true, true, true, false, false, true]);
+        assert_eq!(regions.owner(Coord { x: 0, y: 0 }), Some(Owner::Site(0)));
+        assert_eq!(regions.owner(Coord { x: 5, y: 0 }), Some(Owner::Tie));
+        assert_eq!(regions.owner(Coord { x: 3, y: 3 }), Some(Owner::Site(3)));
+        assert_eq!(regions.owner(Coord { x: 20, y: 0 }), None);
+    }
+
+    #[test]
+    fn test_metrics() {
+        let a = Coord { x: 1, y: 1 };
+        let b = Coord { x: 4, y: -1 };
+        assert_eq!(Metric::Manhattan.distance(&a, &b), 5);
+        assert_eq!(Metric::Chebyshev.distance(&a, &b), 3);
+        assert_eq!(Metric::SquaredEuclidean.distance(&a, &b), 13);
+        assert_eq!(Metric::WeightedManhattan { x_weight: 2, y_weight: 5 }.distance(&a, &b), 16);
+    }
+
+    #[test]
+    fn test_other_metrics() {
+        let coords = parse_coords(&mut COORDS.as_bytes()).expect("Couldn't parse coordinates");
+        let regions = RegionMap::new(&coords, Metric::SquaredEuclidean).unwrap();
+        assert_eq!(regions.infinite, vec![true, true, true, false, false, true]);
+        assert_eq!(largest_non_infinite_area(&coords, Metric::Chebyshev), Some(10));
+        assert_eq!(size_of_close_region(&coords, 32, Metric::Chebyshev), Some(80));
+        // A site in the middle of a hull edge still has an infinite region.
+        let coords = vec![
+            Coord { x: 0, y: 0 },
+            Coord { x: 2, y: 0 },
+            Coord { x: 4, y: 0 },
+            Coord { x: 2, y: 3 },
+            Coord { x: 2, y: 1 },
+        ];
+        let regions = RegionMap::new(&coords, Metric::SquaredEuclidean).unwrap();
+        assert_eq!(regions.infinite, vec![true, true, true, true, false]);
+    }
+
+    #[test]
+    fn test_region_map_matches_brute_force() {
+        let metrics = [
+            Metric::Manhattan,
+            Metric::Chebyshev,
+            Metric::SquaredEuclidean,
+            Metric::WeightedManhattan { x_weight: 2, y_weight: 3 },
+        ];
+        let mut rng = rand::thread_rng();
+        for metric in &metrics {
+            for _ in 0..20 {
+                let coords: Vec<Coord> = (0..rng.gen_range(1, 10))
+                    .map(|_| Coord { x: rng.gen_range(-6, 6), y: rng.gen_range(-6, 6) })
+                    .collect();
+                let regions = RegionMap::new(&coords, *metric).unwrap();
+                // Look a good way past the edge of the map too, to
+                // check that nothing finite escapes it.
+                let margin = 15;
+                let mut areas = vec![0; coords.len()];
+                let mut on_edge = vec![false; coords.len()];
+                for x in regions.bbox.0 - margin..=regions.bbox.1 + margin {
+                    for y in regions.bbox.2 - margin..=regions.bbox.3 + margin {
+                        let point = Coord { x, y };
+                        let min_distance = coords.iter().map(|c| metric.distance(&point, c)).min().unwrap();
+                        let nearest: Vec<usize> = (0..coords.len())
+                            .filter(|&i| metric.distance(&point, &coords[i]) == min_distance)
+                            .collect();
+                        let expected = if nearest.len() == 1 { Owner::Site(nearest[0]) } else { Owner::Tie };
+                        if let Some(owner) = regions.owner(point) {
+                            assert_eq!(owner, expected);
+                        }
+                        if let Owner::Site(site) = expected {
+                            areas[site] += 1;
+                            if x == regions.bbox.0 - margin || x == regions.bbox.1 + margin
+                                || y == regions.bbox.2 - margin || y == regions.bbox.3 + margin {
+                                on_edge[site] = true;
+                            }
+                        }
+                    }
+                }
+                for site in 0..coords.len() {
+                    if regions.infinite[site] {
+                        if *metric != Metric::SquaredEuclidean {
+                            assert!(on_edge[site]);
+                        }
+                    } else {
+                        assert!(!on_edge[site]);
+                        assert_eq!(regions.areas[site], areas[site]);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_close_region_beyond_bounding_box() {
+        let mut rng = rand::thread_rng();
+        let metrics = [Metric::Manhattan, Metric::WeightedManhattan { x_weight: 2, y_weight: 3 }];
+        for metric in &metrics {
+            for _ in 0..20 {
+                let coords: Vec<Coord> = (0..rng.gen_range(1, 6))
+                    .map(|_| Coord { x: rng.gen_range(-5, 5), y: rng.gen_range(-5, 5) })
+                    .collect();
+                let close_distance = rng.gen_range(0, 60);
+                let mut expected_area = 0;
+                let mut expected_outline = vec![];
+                for x in -70..70 {
+                    let ys: Vec<i64> = (-70..70)
+                        .filter(|&y| {
+                            let point = Coord { x, y };
+                            coords.iter().map(|c| metric.distance(&point, c)).sum::<i64>() < close_distance
+                        })
+                        .collect();
+                    expected_area += ys.len() as u64;
+                    if !ys.is_empty() {
+                        expected_outline.push((x, ys[0], ys[ys.len() - 1]));
+                    }
+                }
+                assert_eq!(size_of_close_region(&coords, close_distance, *metric), Some(expected_area));
+                assert_eq!(close_region_outline(&coords, close_distance, *metric), Some(expected_outline));
+            }
+        }
+    }
+
+    #[test]
+    fn test_huge_close_region() {
+        let origin = vec![Coord { x: 0, y: 0 }];
+        let limit: i64 = 1_000_000_000;
+        let expected = 2 * (limit as u64).pow(2) - 2 * limit as u64 + 1;
+        assert_eq!(size_of_close_region(&origin, limit, Metric::Manhattan), Some(expected));
+
+        let limit: i64 = 1_000_000;
+        let metric = Metric::WeightedManhattan { x_weight: 2, y_weight: 3 };
+        // Count each column |x| directly: 2|x| + 3|y| < limit.
+        let expected: u64 = (-limit / 2..=limit / 2)
+            .map(|x: i64| limit - 1 - 2 * x.abs())
+            .filter(|&room| room >= 0)
+            .map(|room| 2 * (room / 3) as u64 + 1)
+            .sum();
+        assert_eq!(size_of_close_region(&origin, limit, metric), Some(expected));
+        assert_eq!(close_region_outline(&origin, 5, metric),
+                   Some(vec![(-2, 0, 0), (-1, 0, 0), (0, -1, 1), (1, 0, 0), (2, 0, 0)]));
+    }
+
+    #[test]
+    fn test_invalid_weights() {
+        let coords = vec![Coord { x: 1, y: 1 }, Coord { x: 4, y: 6 }, Coord { x: 8, y: 3 }];
+        for &(x_weight, y_weight) in &[(0, 1), (1, 0), (-1, 2), (0, 0)] {
+            let metric = Metric::WeightedManhattan { x_weight, y_weight };
+            assert!(!metric.is_valid());
+            assert!(RegionMap::new(&coords, metric).is_none());
+            assert_eq!(largest_non_infinite_area(&coords, metric), None);
+            assert_eq!(size_of_close_region(&coords, 32, metric), None);
+            assert_eq!(close_region_outline(&coords, 32, metric), None);
+        }
+        assert!(Metric::WeightedManhattan { x_weight: 1, y_weight: 1 }.is_valid());
     }
 }
@@ -1,107 +1,821 @@
 use std::io;
-use std::io::BufRead;
-use std::collections::{HashMap, BinaryHeap};
+use std::io::{BufRead, Read};
+use std::fs::File;
+use std::io::BufReader;
+use std::collections::{HashMap, BTreeMap, BTreeSet, BinaryHeap};
 use std::cmp::Reverse;
+use std::fmt::{self, Display, Formatter};
 use regex::Regex;
-use failure::Error;
+use failure::{Error, bail, ensure, format_err};
+use serde::{Serialize, Deserialize};
 
-pub fn aoc7(part2: bool) -> Result<(), Error> {
-    let dependencies = parse_dependency_graph(&mut io::stdin().lock())?;
+pub fn aoc7(part2: bool, durations_path: Option<&str>) -> Result<(), Error> {
+    let (dependencies, details) = parse_dependency_graph(&mut io::stdin().lock())?;
     if part2 {
-        println!("Seconds till completion: {}", seconds_to_completion(&dependencies, 60, 5));
+        let details = with_details_file(details, durations_path)?;
+        println!("Seconds till completion: {}", seconds_to_completion(&dependencies, &puzzle_durations(details.durations), 5)?);
     } else {
-        println!("Topological sort: {}", toposort(&dependencies).unwrap().iter().collect::<String>());
+        println!("Topological sort: {}", join_steps(&toposort(&dependencies)?));
     }
     Ok(())
 }
 
-fn parse_dependency_graph(input: &mut impl BufRead) -> Result<HashMap<char, Vec<char>>, Error> {
-    let edge_regex = Regex::new(r"Step (.) must be finished before step (.) can begin.")?;
-    let mut graph = HashMap::new();
+/// Prints the full schedule as a Gantt chart or CSV, along with each
+/// worker's idle time. Without a workers file, uses the puzzle's five
+/// identical workers.
+pub fn aoc7_schedule(format: &str, workers_path: Option<&str>, durations_path: Option<&str>) -> Result<(), Error> {
+    let (dependencies, details) = parse_dependency_graph(&mut io::stdin().lock())?;
+    let details = with_details_file(details, durations_path)?;
+    let workers = load_workers(workers_path)?;
+    let schedule = schedule(&dependencies, &puzzle_durations(details.durations), &details.required_skills, &workers)?;
+    match format {
+        "gantt" => print!("{}", schedule.to_gantt(80)),
+        "csv" => {
+            print!("{}", schedule.to_csv());
+            // Keep the idle summary out of the CSV itself.
+            for (i, worker) in schedule.workers.iter().enumerate() {
+                eprintln!("{} idle for {} seconds", worker, schedule.idle_time(i));
+            }
+        },
+        _ => bail!("Unknown schedule format {}", format),
+    }
+    Ok(())
+}
+
+/// Prints each step's timing and slack, marking the critical steps,
+/// followed by the critical path and lower bounds on the completion
+/// time.
+pub fn aoc7_critical_path(durations_path: Option<&str>) -> Result<(), Error> {
+    let (dependencies, details) = parse_dependency_graph(&mut io::stdin().lock())?;
+    let details = with_details_file(details, durations_path)?;
+    let analysis = critical_path(&dependencies, &puzzle_durations(details.durations))?;
+    println!("{:10} {:>8} {:>8} {:>8} {:>8}", "step", "duration", "earliest", "latest", "slack");
+    for step in toposort(&dependencies)? {
+        let timing = &analysis.timings[&step];
+        println!("{:10} {:>8} {:>8} {:>8} {:>8}{}", step, timing.duration, timing.earliest_start, timing.latest_start, timing.slack,
+                 if timing.slack == 0 { " *" } else { "" });
+    }
+    println!("Critical path: {}", analysis.critical_path.join(" -> "));
+    println!("Minimum time with unlimited workers: {}", analysis.makespan);
+    println!("Minimum time with 5 workers: at least {}", analysis.makespan_lower_bound(5));
+    Ok(())
+}
+
+/// Reads the graph in puzzle syntax or JSON and writes it back out as
+/// Graphviz DOT, JSON or puzzle syntax. The DOT output highlights the
+/// critical path when every step's duration is known, and both DOT and
+/// JSON can include a computed schedule.
+pub fn aoc7_export(format: &str, from_json: bool, with_schedule: bool, workers_path: Option<&str>,
+                   durations_path: Option<&str>) -> Result<(), Error> {
+    let (dependencies, details) = if from_json {
+        let mut json = String::new();
+        io::stdin().read_to_string(&mut json)?;
+        from_json_document(&json)?
+    } else {
+        parse_dependency_graph(&mut io::stdin().lock())?
+    };
+    let details = with_details_file(details, durations_path)?;
+    let durations = puzzle_durations(details.durations.clone());
+    let schedule = if with_schedule {
+        Some(schedule(&dependencies, &durations, &details.required_skills, &load_workers(workers_path)?)?)
+    } else {
+        None
+    };
+    match format {
+        "dot" => {
+            // A graph with cycles or unknown durations is still worth
+            // drawing, just without the critical path.
+            let analysis = critical_path(&dependencies, &durations).ok();
+            print!("{}", to_dot(&dependencies, analysis.as_ref(), schedule.as_ref()));
+        },
+        "json" => println!("{}", to_json(&dependencies, &details, schedule.as_ref())?),
+        "steps" => print!("{}", to_puzzle_syntax(&dependencies, &details)),
+        _ => bail!("Unknown export format {}", format),
+    }
+    Ok(())
+}
+
+/// Workers from a file, or the puzzle's five identical workers.
+fn load_workers(path: Option<&str>) -> Result<Vec<Worker>, Error> {
+    match path {
+        Some(path) => parse_workers(&mut BufReader::new(File::open(path)?)),
+        None => Ok((1..=5).map(|i| Worker::new(&format!("worker{}", i))).collect()),
+    }
+}
+
+/// Merges step details from a separate file, if given, over the ones
+/// from the puzzle input.
+fn with_details_file(mut details: StepDetails, path: Option<&str>) -> Result<StepDetails, Error> {
+    if let Some(path) = path {
+        let extra = parse_step_details(&mut BufReader::new(File::open(path)?))?;
+        details.durations.extend(extra.durations);
+        details.required_skills.extend(extra.required_skills);
+    }
+    Ok(details)
+}
+
+/// Listed durations, falling back to the puzzle's alphabetical rule.
+fn puzzle_durations(durations: HashMap<String, u64>) -> DurationPolicy {
+    DurationPolicy::Table {
+        durations,
+        fallback: Some(Box::new(DurationPolicy::Alphabetical { base_seconds: 60 })),
+    }
+}
+
+/// Lists every strongly connected component of the graph, one per
+/// line. Components with more than one step are dependency cycles.
+pub fn aoc7_components() -> Result<(), Error> {
+    let (dependencies, _) = parse_dependency_graph(&mut io::stdin().lock())?;
+    for component in strongly_connected_components(&dependencies) {
+        let cyclic = component.len() > 1 || dependencies.get(&component[0]).is_some_and(|d| d.contains(&component[0]));
+        println!("{}{}", join_steps(&component), if cyclic { " (cycle)" } else { "" });
+    }
+    Ok(())
+}
+
+/// Single-letter steps are run together like the puzzle answer
+/// expects; longer names are separated by spaces.
+fn join_steps(steps: &[String]) -> String {
+    if steps.iter().all(|s| s.chars().count() == 1) {
+        steps.concat()
+    } else {
+        steps.join(" ")
+    }
+}
+
+/// Maps each step to the steps that can't begin until it's finished.
+pub type DependencyGraph = HashMap<String, Vec<String>>;
+
+/// How long each step takes to complete.
+pub enum DurationPolicy {
+    /// The puzzle's rule: `base_seconds` plus the step's position in
+    /// the alphabet, so A takes `base_seconds + 1`. Only works for
+    /// single-letter steps.
+    Alphabetical { base_seconds: u64 },
+    /// Durations looked up by step name. Steps missing from the table
+    /// use the fallback policy, or are an error if there isn't one.
+    Table { durations: HashMap<String, u64>, fallback: Option<Box<DurationPolicy>> },
+}
+
+impl DurationPolicy {
+    pub fn duration(&self, step: &str) -> Result<u64, Error> {
+        match self {
+            DurationPolicy::Alphabetical { base_seconds } => {
+                let mut chars = step.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(letter @ 'A'..='Z'), None) => Ok(base_seconds + u64::from(letter as u8 - b'A' + 1)),
+                    _ => bail!("Step {} isn't a single capital letter, so it has no alphabetical duration", step),
+                }
+            },
+            DurationPolicy::Table { durations, fallback } => match (durations.get(step), fallback) {
+                (Some(&duration), _) => Ok(duration),
+                (None, Some(fallback)) => fallback.duration(step),
+                (None, None) => bail!("No duration given for step {}", step),
+            },
+        }
+    }
+}
+
+/// Extra information about steps beyond their dependencies.
+#[derive(Debug, Default, PartialEq)]
+pub struct StepDetails {
+    pub durations: HashMap<String, u64>,
+    /// The skill a worker needs to be assigned the step.
+    pub required_skills: HashMap<String, String>,
+}
+
+/// Parses the puzzle's dependency lines, optionally mixed with
+/// `name duration [skill]` lines giving how long a step takes and what
+/// skill it needs. Steps that only appear in a duration line are added
+/// to the graph without any dependencies.
+fn parse_dependency_graph(input: &mut impl BufRead) -> Result<(DependencyGraph, StepDetails), Error> {
+    let edge_regex = Regex::new(r"^Step (\S+) must be finished before step (\S+) can begin\.$")?;
+    let mut graph: DependencyGraph = HashMap::new();
+    let mut duration_lines = String::new();
     for line_res in input.lines() {
         let line = line_res?;
-        let captures = edge_regex.captures(&line).unwrap();
-        let required_step = captures.get(1).unwrap().as_str().chars().last().unwrap();
-        let dependent_step = captures.get(2).unwrap().as_str().chars().last().unwrap();
-        (*graph.entry(required_step).or_insert_with(|| vec![])).push(dependent_step);
+        match edge_regex.captures(line.trim()) {
+            Some(captures) => {
+                graph.entry(captures[1].to_string()).or_default().push(captures[2].to_string());
+            },
+            None => {
+                duration_lines.push_str(&line);
+                duration_lines.push('\n');
+            },
+        }
+    }
+    let details = parse_step_details(&mut duration_lines.as_bytes())?;
+    let known_steps = all_steps(&graph);
+    let unconnected_steps: Vec<String> = details.durations.keys().filter(|step| !known_steps.contains(step.as_str())).cloned().collect();
+    for step in unconnected_steps {
+        graph.insert(step, vec![]);
     }
-    Ok(graph)
+    Ok((graph, details))
 }
 
-fn toposort(graph: &HashMap<char, Vec<char>>) -> Result<Vec<char>, Error> {
-    let mut incoming_edges: HashMap<char, u64> = HashMap::new();
+/// Parses `name duration [skill]` lines, skipping blank lines.
+fn parse_step_details(input: &mut impl BufRead) -> Result<StepDetails, Error> {
+    let mut details = StepDetails::default();
+    for line_res in input.lines() {
+        let line = line_res?;
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.is_empty() {
+            continue;
+        }
+        ensure!(fields.len() == 2 || fields.len() == 3, "Can't understand line {}", line);
+        let duration: u64 = fields[1].parse().map_err(|_| format_err!("Can't understand duration in line {}", line))?;
+        ensure!(details.durations.insert(fields[0].to_string(), duration).is_none(), "Duration for step {} given twice", fields[0]);
+        if let Some(skill) = fields.get(2) {
+            details.required_skills.insert(fields[0].to_string(), skill.to_string());
+        }
+    }
+    Ok(details)
+}
+
+/// Someone who can work on steps.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Worker {
+    pub name: String,
+    /// How much faster than normal this worker finishes steps; a
+    /// worker with speed 2 takes half as long.
+    pub speed: f64,
+    pub skills: BTreeSet<String>,
+}
+
+impl Worker {
+    /// A normal-speed worker with no special skills.
+    pub fn new(name: &str) -> Self {
+        Worker { name: name.to_string(), speed: 1.0, skills: BTreeSet::new() }
+    }
+
+    fn can_work_on(&self, required_skill: Option<&String>) -> bool {
+        required_skill.map_or(true, |skill| self.skills.contains(skill))
+    }
+
+    /// Time this worker needs for a step that normally takes
+    /// `duration`, rounded up to a whole second.
+    fn time_for(&self, duration: u64) -> u64 {
+        (duration as f64 / self.speed).ceil() as u64
+    }
+}
+
+impl Display for Worker {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+/// Parses `name speed [skill...]` lines, skipping blank lines.
+fn parse_workers(input: &mut impl BufRead) -> Result<Vec<Worker>, Error> {
+    let mut workers = vec![];
+    for line_res in input.lines() {
+        let line = line_res?;
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.is_empty() {
+            continue;
+        }
+        ensure!(fields.len() >= 2, "Can't understand line {}", line);
+        let speed: f64 = fields[1].parse().map_err(|_| format_err!("Can't understand speed in line {}", line))?;
+        ensure!(speed > 0.0 && speed.is_finite(), "Worker {} needs a positive speed", fields[0]);
+        workers.push(Worker {
+            name: fields[0].to_string(),
+            speed,
+            skills: fields[2..].iter().map(|s| s.to_string()).collect(),
+        });
+    }
+    Ok(workers)
+}
+
+fn count_incoming_edges(graph: &DependencyGraph) -> HashMap<&str, u64> {
+    let mut incoming_edges: HashMap<&str, u64> = HashMap::new();
     for dependents in graph.values() {
         for dependent in dependents {
-            *incoming_edges.entry(*dependent).or_insert(0) += 1;
+            *incoming_edges.entry(dependent).or_insert(0) += 1;
         }
     }
+    incoming_edges
+}
 
+/// Every step mentioned in the graph, whether or not it has dependents.
+fn all_steps(graph: &DependencyGraph) -> BTreeSet<&str> {
+    graph.iter().flat_map(|(step, dependents)| Some(step).into_iter().chain(dependents)).map(|s| s.as_str()).collect()
+}
+
+/// Steps with no dependencies, ready to start right away.
+fn initially_ready<'a>(graph: &'a DependencyGraph, incoming_edges: &HashMap<&str, u64>) -> BinaryHeap<Reverse<&'a str>> {
+    graph.keys().map(|s| s.as_str()).filter(|n| !incoming_edges.contains_key(n)).map(Reverse).collect()
+}
+
+/// Returns an error naming one dependency cycle if any step was left
+/// with unmet dependencies once no more steps could be started.
+fn ensure_acyclic(graph: &DependencyGraph, incoming_edges: &HashMap<&str, u64>) -> Result<(), Error> {
+    let blocked: BTreeSet<&str> = incoming_edges.iter().filter(|(_, &count)| count > 0).map(|(&step, _)| step).collect();
+    let start = match blocked.iter().next() {
+        Some(&step) => step,
+        None => return Ok(()),
+    };
+    // Every blocked step has a blocked requirement, so walking
+    // requirements backwards must eventually revisit a step.
+    let mut requirements: HashMap<&str, BTreeSet<&str>> = HashMap::new();
+    for (step, dependents) in graph {
+        if blocked.contains(step.as_str()) {
+            for dependent in dependents.iter().filter(|d| blocked.contains(d.as_str())) {
+                requirements.entry(dependent).or_default().insert(step);
+            }
+        }
+    }
+    let mut path = vec![start];
+    let mut position_in_path: HashMap<&str, usize> = HashMap::new();
+    position_in_path.insert(start, 0);
+    loop {
+        let current = path[path.len() - 1];
+        let requirement = *requirements[current].iter().next().unwrap();
+        if let Some(&position) = position_in_path.get(requirement) {
+            let mut cycle: Vec<&str> = path[position..].iter().rev().cloned().collect();
+            // Start the report at the alphabetically first step so
+            // it doesn't depend on where the walk began.
+            let first = (0..cycle.len()).min_by_key(|&i| cycle[i]).unwrap();
+            cycle.rotate_left(first);
+            cycle.push(cycle[0]);
+            bail!("Dependency cycle: {}", cycle.join(" -> "));
+        }
+        position_in_path.insert(requirement, path.len());
+        path.push(requirement);
+    }
+}
+
+/// Finds the strongly connected components of the graph using
+/// Tarjan's algorithm, with an explicit stack so that long dependency
+/// chains can't overflow the call stack. Components are listed in
+/// dependency order, each sorted alphabetically.
+pub fn strongly_connected_components(graph: &DependencyGraph) -> Vec<Vec<String>> {
+    let no_dependents = vec![];
+    let mut index: HashMap<&str, usize> = HashMap::new();
+    let mut lowlink: HashMap<&str, usize> = HashMap::new();
+    let mut on_stack: BTreeSet<&str> = BTreeSet::new();
+    let mut stack: Vec<&str> = vec![];
+    let mut components = vec![];
+    let mut next_index = 0;
+    for root in all_steps(graph) {
+        if index.contains_key(root) {
+            continue;
+        }
+        // Each frame is a step and how many of its dependents we've
+        // visited so far.
+        let mut frames: Vec<(&str, usize)> = vec![(root, 0)];
+        index.insert(root, next_index);
+        lowlink.insert(root, next_index);
+        next_index += 1;
+        stack.push(root);
+        on_stack.insert(root);
+        while let Some(&mut (step, ref mut visited)) = frames.last_mut() {
+            let dependents = graph.get(step).unwrap_or(&no_dependents);
+            if let Some(dependent) = dependents.get(*visited) {
+                let dependent = dependent.as_str();
+                *visited += 1;
+                match index.get(dependent) {
+                    Some(&dependent_index) => if on_stack.contains(dependent) {
+                        let low = lowlink[step].min(dependent_index);
+                        lowlink.insert(step, low);
+                    },
+                    None => {
+                        index.insert(dependent, next_index);
+                        lowlink.insert(dependent, next_index);
+                        next_index += 1;
+                        stack.push(dependent);
+                        on_stack.insert(dependent);
+                        frames.push((dependent, 0));
+                    },
+                }
+                continue;
+            }
+            frames.pop();
+            if let Some(&(parent, _)) = frames.last() {
+                let low = lowlink[parent].min(lowlink[step]);
+                lowlink.insert(parent, low);
+            }
+            if lowlink[step] == index[step] {
+                let mut component = vec![];
+                loop {
+                    let member = stack.pop().unwrap();
+                    on_stack.remove(member);
+                    component.push(member.to_string());
+                    if member == step {
+                        break;
+                    }
+                }
+                component.sort();
+                components.push(component);
+            }
+        }
+    }
+    // Tarjan's algorithm emits components dependents-first.
+    components.reverse();
+    components
+}
+
+fn toposort(graph: &DependencyGraph) -> Result<Vec<String>, Error> {
+    let mut incoming_edges = count_incoming_edges(graph);
+
+    let no_dependents = vec![];
     let mut sorted = vec![];
     // Nodes that have all their dependencies met. We use a heap to
-    // ensure we always get the alphabetically first character that is
+    // ensure we always get the alphabetically first step that is
     // ready.
-    let mut ready_nodes: BinaryHeap<Reverse<char>> = graph.keys().filter(|n| !incoming_edges.contains_key(n)).map(|&r| Reverse(r)).collect();
-    while !ready_nodes.is_empty() {
-        let node = ready_nodes.pop().unwrap();
-        sorted.push(node.0);
-        for adjacency in graph.get(&node.0).unwrap_or(&vec![]) {
-            *incoming_edges.get_mut(adjacency).unwrap() -= 1;
-            if incoming_edges[adjacency] == 0 {
-                ready_nodes.push(Reverse(*adjacency));
+    let mut ready_nodes = initially_ready(graph, &incoming_edges);
+    while let Some(Reverse(node)) = ready_nodes.pop() {
+        sorted.push(node.to_string());
+        for adjacency in graph.get(node).unwrap_or(&no_dependents) {
+            let incoming = incoming_edges.get_mut(adjacency.as_str()).unwrap();
+            *incoming -= 1;
+            if *incoming == 0 {
+                ready_nodes.push(Reverse(adjacency));
             }
         }
     }
+    ensure_acyclic(graph, &incoming_edges)?;
     Ok(sorted)
 }
 
-#[derive(PartialEq, Eq, PartialOrd, Ord)]
-struct WorkedNode {
-    completion_time: u64,
-    node: char,
+/// One step done by one worker.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Assignment {
+    /// Index into `Schedule::workers`.
+    pub worker: usize,
+    pub step: String,
+    pub start: u64,
+    pub end: u64,
+}
+
+/// Who did what and when.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schedule {
+    pub workers: Vec<Worker>,
+    /// Assignments in the order they were started.
+    pub assignments: Vec<Assignment>,
+    /// When the last step finished.
+    pub makespan: u64,
 }
 
-impl WorkedNode {
-    fn new(node: char, base_seconds: u64, cur_time: u64) -> Self {
-        let a: u8 = b"A"[0];
-        let mut char: [u8; 4] = [0; 4];
-        node.encode_utf8(&mut char);
-        WorkedNode {
-            node,
-            completion_time: cur_time + base_seconds + u64::from(char[0] - a + 1),
+impl Schedule {
+    /// Seconds the worker spent without a step before the last step
+    /// finished.
+    pub fn idle_time(&self, worker: usize) -> u64 {
+        let busy: u64 = self.assignments.iter().filter(|a| a.worker == worker).map(|a| a.end - a.start).sum();
+        self.makespan - busy
+    }
+
+    /// Draws a row per worker, squeezing the timeline into at most
+    /// `width` columns. Each step is drawn as a bar starting with its
+    /// name; idle time is drawn as dots. Steps that take no time have
+    /// no bar, so they're listed after the row instead.
+    pub fn to_gantt(&self, width: usize) -> String {
+        let seconds_per_column = std::cmp::max(1, self.makespan.div_ceil(width.max(1) as u64));
+        let columns = self.makespan.div_ceil(seconds_per_column) as usize;
+        let name_width = self.workers.iter().map(|w| w.name.chars().count()).max().unwrap_or(0);
+        let mut chart = if columns == 0 {
+            format!("{:name_width$} 0\n", "", name_width = name_width)
+        } else {
+            format!("{:name_width$} 0{:>width$}\n", "", self.makespan, name_width = name_width, width = columns)
+        };
+        for (i, worker) in self.workers.iter().enumerate() {
+            let mut row = vec!['.'; columns];
+            let mut zero_length = vec![];
+            for assignment in self.assignments.iter().filter(|a| a.worker == i) {
+                if assignment.start == assignment.end {
+                    zero_length.push(format!("{} at {}", assignment.step, assignment.start));
+                    continue;
+                }
+                let start = (assignment.start / seconds_per_column) as usize;
+                let end = std::cmp::max(start + 1, assignment.end.div_ceil(seconds_per_column) as usize);
+                let label = assignment.step.chars().chain(std::iter::repeat('='));
+                for (cell, label_char) in row[start..end].iter_mut().zip(label) {
+                    *cell = label_char;
+                }
+            }
+            chart += &format!("{:name_width$} |{}| idle {}", worker.name, row.iter().collect::<String>(), self.idle_time(i),
+                              name_width = name_width);
+            if !zero_length.is_empty() {
+                chart += &format!("; zero-length: {}", zero_length.join(", "));
+            }
+            chart += "\n";
+        }
+        if seconds_per_column > 1 {
+            chart += &format!("(each column is {} seconds)\n", seconds_per_column);
+        }
+        chart
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut csv = "worker,step,start,end\n".to_string();
+        for assignment in &self.assignments {
+            csv += &format!("{},{},{},{}\n", self.workers[assignment.worker], assignment.step, assignment.start, assignment.end);
         }
+        csv
     }
 }
 
-fn seconds_to_completion(graph: &HashMap<char, Vec<char>>, base_seconds: u64, num_workers: usize) -> u64 {
-    let mut incoming_edges: HashMap<char, u64> = HashMap::new();
-    for dependents in graph.values() {
-        for dependent in dependents {
-            *incoming_edges.entry(*dependent).or_insert(0) += 1;
-        }
+/// Simulates working through the steps. Whenever workers are free,
+/// ready steps are handed out alphabetically, each to the fastest free
+/// worker that has the skill it requires. Steps no free worker can do
+/// wait until a suitable worker frees up.
+pub fn schedule(graph: &DependencyGraph, durations: &DurationPolicy, required_skills: &HashMap<String, String>,
+                workers: &[Worker]) -> Result<Schedule, Error> {
+    for step in all_steps(graph) {
+        let skill = required_skills.get(step);
+        ensure!(workers.iter().any(|w| w.can_work_on(skill)),
+                "No worker can do step {}, which needs skill {}", step, skill.map_or("(none)", |s| s.as_str()));
     }
-    let mut ready_nodes: BinaryHeap<Reverse<char>> = graph.keys().filter(|n| !incoming_edges.contains_key(n)).map(|&r| Reverse(r)).collect();
-    let mut nodes_in_progress: BinaryHeap<Reverse<WorkedNode>> = BinaryHeap::new();
+    let no_dependents = vec![];
+    let mut incoming_edges = count_incoming_edges(graph);
+    let mut ready_nodes: BTreeSet<&str> = initially_ready(graph, &incoming_edges).into_iter().map(|r| r.0).collect();
+    // Workers ordered fastest first, so handing each step to the first
+    // free capable worker picks the fastest one.
+    let mut by_speed: Vec<usize> = (0..workers.len()).collect();
+    by_speed.sort_by(|&a, &b| workers[b].speed.total_cmp(&workers[a].speed));
+    let mut free = vec![true; workers.len()];
+    let mut nodes_in_progress: BinaryHeap<Reverse<(u64, usize)>> = BinaryHeap::new();
+    let mut assignments: Vec<Assignment> = vec![];
     let mut cur_time: u64 = 0;
-    while !nodes_in_progress.is_empty() || !ready_nodes.is_empty() {
-        if !ready_nodes.is_empty() && nodes_in_progress.len() < num_workers {
-            // We can take a ready node and begin it.
-            let node = ready_nodes.pop().unwrap();
-            nodes_in_progress.push(Reverse(WorkedNode::new(node.0, base_seconds, cur_time)));
-            continue;
+    loop {
+        let startable: Vec<&str> = ready_nodes.iter().cloned().collect();
+        for node in startable {
+            let skill = required_skills.get(node);
+            if let Some(&worker) = by_speed.iter().find(|&&w| free[w] && workers[w].can_work_on(skill)) {
+                ready_nodes.remove(node);
+                free[worker] = false;
+                let end = cur_time + workers[worker].time_for(durations.duration(node)?);
+                nodes_in_progress.push(Reverse((end, assignments.len())));
+                assignments.push(Assignment { worker, step: node.to_string(), start: cur_time, end });
+            }
         }
-        if let Some(worked_node) = nodes_in_progress.pop() {
-            let completion_time = worked_node.0.completion_time;
-            assert!(cur_time <= completion_time);
-            cur_time = completion_time;
-            for adjacency in graph.get(&worked_node.0.node).unwrap_or(&vec![]) {
-                *incoming_edges.get_mut(adjacency).unwrap() -= 1;
-                if incoming_edges[adjacency] == 0 {
-                    ready_nodes.push(Reverse(*adjacency));
+        let completion_time = match nodes_in_progress.peek() {
+            Some(Reverse((end, _))) => *end,
+            None => break,
+        };
+        cur_time = completion_time;
+        // Finish everything completing now before handing out more
+        // steps, so every newly ready step gets a fair chance.
+        while let Some(&Reverse((end, index))) = nodes_in_progress.peek() {
+            if end != cur_time {
+                break;
+            }
+            nodes_in_progress.pop();
+            let assignment = &assignments[index];
+            free[assignment.worker] = true;
+            for adjacency in graph.get(&assignment.step).unwrap_or(&no_dependents) {
+                let incoming = incoming_edges.get_mut(adjacency.as_str()).unwrap();
+                *incoming -= 1;
+                if *incoming == 0 {
+                    ready_nodes.insert(adjacency);
                 }
             }
         }
     }
-    cur_time
+    ensure_acyclic(graph, &incoming_edges)?;
+    Ok(Schedule { workers: workers.to_vec(), assignments, makespan: cur_time })
+}
+
+fn seconds_to_completion(graph: &DependencyGraph, durations: &DurationPolicy, num_workers: usize) -> Result<u64, Error> {
+    let workers: Vec<Worker> = (0..num_workers).map(|i| Worker::new(&format!("worker{}", i + 1))).collect();
+    Ok(schedule(graph, durations, &HashMap::new(), &workers)?.makespan)
+}
+
+/// When a step can run without delaying completion of the whole graph.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StepTiming {
+    pub duration: u64,
+    /// Earliest the step can start, once everything it needs is done.
+    pub earliest_start: u64,
+    /// Latest the step can start without delaying completion.
+    pub latest_start: u64,
+    /// How long the step can be delayed; zero for critical steps.
+    pub slack: u64,
+}
+
+/// Critical path method analysis, assuming unlimited workers.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CriticalPathAnalysis {
+    pub timings: BTreeMap<String, StepTiming>,
+    /// A longest chain of dependent steps. Speeding up anything else
+    /// won't finish the graph any sooner.
+    pub critical_path: Vec<String>,
+    /// Length of the critical path, which is how long the graph takes
+    /// with unlimited workers.
+    pub makespan: u64,
+}
+
+impl CriticalPathAnalysis {
+    /// No schedule on `num_workers` identical workers can finish before
+    /// the critical path does, or before the total work is shared out
+    /// perfectly evenly.
+    pub fn makespan_lower_bound(&self, num_workers: usize) -> u64 {
+        let total_work: u64 = self.timings.values().map(|t| t.duration).sum();
+        std::cmp::max(self.makespan, total_work.div_ceil(num_workers.max(1) as u64))
+    }
+}
+
+pub fn critical_path(graph: &DependencyGraph, durations: &DurationPolicy) -> Result<CriticalPathAnalysis, Error> {
+    let no_dependents = vec![];
+    let order = toposort(graph)?;
+    let mut duration: HashMap<&str, u64> = HashMap::new();
+    for step in &order {
+        duration.insert(step, durations.duration(step)?);
+    }
+
+    // Forward pass: a step can start once all its requirements finish.
+    let mut earliest_start: HashMap<&str, u64> = order.iter().map(|s| (s.as_str(), 0)).collect();
+    for step in &order {
+        let finish = earliest_start[step.as_str()] + duration[step.as_str()];
+        for dependent in graph.get(step).unwrap_or(&no_dependents) {
+            let start = earliest_start.get_mut(dependent.as_str()).unwrap();
+            *start = std::cmp::max(*start, finish);
+        }
+    }
+    let makespan = order.iter().map(|s| earliest_start[s.as_str()] + duration[s.as_str()]).max().unwrap_or(0);
+
+    // Backward pass: a step must finish before any dependent's latest
+    // start.
+    let mut latest_start: HashMap<&str, u64> = HashMap::new();
+    for step in order.iter().rev() {
+        let latest_finish = graph.get(step).unwrap_or(&no_dependents).iter()
+            .map(|d| latest_start[d.as_str()])
+            .min()
+            .unwrap_or(makespan);
+        latest_start.insert(step, latest_finish - duration[step.as_str()]);
+    }
+
+    let timings: BTreeMap<String, StepTiming> = order.iter().map(|step| {
+        let step = step.as_str();
+        (step.to_string(), StepTiming {
+            duration: duration[step],
+            earliest_start: earliest_start[step],
+            latest_start: latest_start[step],
+            slack: latest_start[step] - earliest_start[step],
+        })
+    }).collect();
+
+    // Follow critical steps that start exactly when the previous one
+    // finishes, taking the alphabetically first at each fork.
+    let is_next_critical = |step: &str, after: u64| timings[step].slack == 0 && timings[step].earliest_start == after;
+    let mut critical_path = vec![];
+    let mut current = timings.keys().find(|step| is_next_critical(step, 0)).cloned();
+    while let Some(step) = current {
+        let finish = timings[&step].earliest_start + timings[&step].duration;
+        current = graph.get(&step).unwrap_or(&no_dependents).iter()
+            .filter(|d| is_next_critical(d, finish))
+            .min()
+            .cloned();
+        critical_path.push(step);
+    }
+
+    Ok(CriticalPathAnalysis { timings, critical_path, makespan })
+}
+
+/// Quotes a string for use as a Graphviz ID or label, with newlines
+/// becoming line breaks.
+fn dot_quote(name: &str) -> String {
+    format!("\"{}\"", name.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n"))
+}
+
+/// Renders the graph in Graphviz DOT. Critical steps (those with no
+/// slack) and the edges between them that leave no gap are drawn in
+/// red, and scheduled steps are labelled with who worked on them and
+/// when.
+pub fn to_dot(graph: &DependencyGraph, analysis: Option<&CriticalPathAnalysis>, schedule: Option<&Schedule>) -> String {
+    let timing = |step: &str| analysis.and_then(|a| a.timings.get(step));
+    let critical = |step: &str| timing(step).is_some_and(|t| t.slack == 0);
+    let mut dot = "digraph steps {\n".to_string();
+    for step in all_steps(graph) {
+        let mut label = step.to_string();
+        if let Some(timing) = timing(step) {
+            label += &format!("\n{}s, slack {}", timing.duration, timing.slack);
+        }
+        if let Some(schedule) = schedule {
+            for assignment in schedule.assignments.iter().filter(|a| a.step == step) {
+                label += &format!("\n{} {}-{}", schedule.workers[assignment.worker], assignment.start, assignment.end);
+            }
+        }
+        let highlight = if critical(step) { ", color=red, penwidth=2" } else { "" };
+        dot += &format!("    {} [label={}{}];\n", dot_quote(step), dot_quote(&label), highlight);
+    }
+    let mut edges: Vec<(&String, &String)> = graph.iter()
+        .flat_map(|(step, dependents)| dependents.iter().map(move |d| (step, d)))
+        .collect();
+    edges.sort();
+    for (step, dependent) in edges {
+        let critical_edge = match (timing(step), timing(dependent)) {
+            (Some(before), Some(after)) =>
+                before.slack == 0 && after.slack == 0 && before.earliest_start + before.duration == after.earliest_start,
+            _ => false,
+        };
+        dot += &format!("    {} -> {}{};\n", dot_quote(step), dot_quote(dependent),
+                        if critical_edge { " [color=red, penwidth=2]" } else { "" });
+    }
+    dot += "}\n";
+    dot
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct GraphDocument {
+    steps: Vec<StepDocument>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    schedule: Option<ScheduleDocument>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct StepDocument {
+    name: String,
+    #[serde(default)]
+    dependents: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    duration: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    required_skill: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ScheduleDocument {
+    makespan: u64,
+    assignments: Vec<AssignmentDocument>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct AssignmentDocument {
+    worker: String,
+    step: String,
+    start: u64,
+    end: u64,
+}
+
+/// Writes the graph, any explicitly given step details and optionally
+/// a schedule as JSON. Every step gets an entry, sorted by name.
+pub fn to_json(graph: &DependencyGraph, details: &StepDetails, schedule: Option<&Schedule>) -> Result<String, Error> {
+    let document = GraphDocument {
+        steps: all_steps(graph).into_iter().map(|step| StepDocument {
+            name: step.to_string(),
+            dependents: graph.get(step).cloned().unwrap_or_default(),
+            duration: details.durations.get(step).cloned(),
+            required_skill: details.required_skills.get(step).cloned(),
+        }).collect(),
+        schedule: schedule.map(|schedule| ScheduleDocument {
+            makespan: schedule.makespan,
+            assignments: schedule.assignments.iter().map(|a| AssignmentDocument {
+                worker: schedule.workers[a.worker].name.clone(),
+                step: a.step.clone(),
+                start: a.start,
+                end: a.end,
+            }).collect(),
+        }),
+    };
+    Ok(serde_json::to_string_pretty(&document)?)
+}
+
+/// Reads a graph written by `to_json`. Any schedule in the document is
+/// ignored, since it's derived from the graph.
+pub fn from_json_document(json: &str) -> Result<(DependencyGraph, StepDetails), Error> {
+    let document: GraphDocument = serde_json::from_str(json)?;
+    let mentioned: BTreeSet<&str> = document.steps.iter().flat_map(|s| &s.dependents).map(|s| s.as_str()).collect();
+    let mut graph = DependencyGraph::new();
+    let mut details = StepDetails::default();
+    let mut seen = BTreeSet::new();
+    for step in &document.steps {
+        ensure!(seen.insert(step.name.as_str()), "Step {} listed twice", step.name);
+        // Match the puzzle parser, which only has entries for steps
+        // with dependents or that nothing else mentions.
+        if !step.dependents.is_empty() || !mentioned.contains(step.name.as_str()) {
+            graph.insert(step.name.clone(), step.dependents.clone());
+        }
+        if let Some(duration) = step.duration {
+            details.durations.insert(step.name.clone(), duration);
+        }
+        if let Some(skill) = &step.required_skill {
+            details.required_skills.insert(step.name.clone(), skill.clone());
+        }
+    }
+    Ok((graph, details))
+}
+
+/// Writes the graph back out in the puzzle's syntax, followed by a
+/// `name duration [skill]` line for each step with a known duration.
+/// Skills for steps without a duration, and unconnected steps without
+/// a duration, can't be expressed and are left out.
+pub fn to_puzzle_syntax(graph: &DependencyGraph, details: &StepDetails) -> String {
+    let mut output = String::new();
+    let sorted_graph: BTreeMap<&String, &Vec<String>> = graph.iter().collect();
+    for (step, dependents) in sorted_graph {
+        for dependent in dependents {
+            output += &format!("Step {} must be finished before step {} can begin.\n", step, dependent);
+        }
+    }
+    let sorted_durations: BTreeMap<&String, &u64> = details.durations.iter().collect();
+    for (step, duration) in sorted_durations {
+        match details.required_skills.get(step) {
+            Some(skill) => output += &format!("{} {} {}\n", step, duration, skill),
+            None => output += &format!("{} {}\n", step, duration),
+        }
+    }
+    output
 }
 
 #[cfg(test)]
@@ -109,6 +823,10 @@ mod tests {
     use super::*;
     use std::fmt::Debug;
 
+    fn names(steps: &[&str]) -> Vec<String> {
+        steps.iter().map(|s| s.to_string()).collect()
+    }
+
     /// This function allows us to assert that a Result is
     /// Ok(expected) without requiring PartialEq on the Error type.
     fn assert_result_ok<T: Debug + PartialEq>(r: Result<T, Error>, expected: T) {
@@ -127,8 +845,8 @@ Step A must be finished before step D can begin.
 Step B must be finished before step E can begin.
 Step D must be finished before step E can begin.
 Step F must be finished before step E can begin.";
-        let graph = parse_dependency_graph(&mut steps.as_bytes()).expect("Parsing steps failed");
-        assert_result_ok(toposort(&graph), vec!['C', 'A', 'B', 'D', 'F', 'E']);
+        let (graph, _) = parse_dependency_graph(&mut steps.as_bytes()).expect("Parsing steps failed");
+        assert_result_ok(toposort(&graph), names(&["C", "A", "B", "D", "F", "E"]));
     }
 
     #[test]
@@ -140,7 +858,288 @@ Step A must be finished before step D can begin.
 Step B must be finished before step E can begin.
 Step D must be finished before step E can begin.
 Step F must be finished before step E can begin.";
-        let graph = parse_dependency_graph(&mut steps.as_bytes()).expect("Parsing steps failed");
-        assert_eq!(seconds_to_completion(&graph, 0, 2), 15);
+        let (graph, _) = parse_dependency_graph(&mut steps.as_bytes()).expect("Parsing steps failed");
+        assert_result_ok(seconds_to_completion(&graph, &DurationPolicy::Alphabetical { base_seconds: 0 }, 2), 15);
+    }
+
+    #[test]
+    fn test_cycle_detection() {
+        let steps = "Step C must be finished before step A can begin.
+Step A must be finished before step B can begin.
+Step B must be finished before step D can begin.
+Step D must be finished before step A can begin.
+Step D must be finished before step E can begin.";
+        let (graph, _) = parse_dependency_graph(&mut steps.as_bytes()).expect("Parsing steps failed");
+        let error = toposort(&graph).expect_err("Cycle not detected");
+        assert_eq!(error.to_string(), "Dependency cycle: A -> B -> D -> A");
+        let error = seconds_to_completion(&graph, &DurationPolicy::Alphabetical { base_seconds: 0 }, 2).expect_err("Cycle not detected");
+        assert_eq!(error.to_string(), "Dependency cycle: A -> B -> D -> A");
+
+        let self_loop = "Step A must be finished before step A can begin.";
+        let (graph, _) = parse_dependency_graph(&mut self_loop.as_bytes()).expect("Parsing steps failed");
+        assert_eq!(toposort(&graph).expect_err("Cycle not detected").to_string(), "Dependency cycle: A -> A");
+    }
+
+    #[test]
+    fn test_strongly_connected_components() {
+        let steps = "Step C must be finished before step A can begin.
+Step A must be finished before step B can begin.
+Step B must be finished before step D can begin.
+Step D must be finished before step A can begin.
+Step D must be finished before step E can begin.
+Step E must be finished before step F can begin.
+Step F must be finished before step E can begin.";
+        let (graph, _) = parse_dependency_graph(&mut steps.as_bytes()).expect("Parsing steps failed");
+        assert_eq!(strongly_connected_components(&graph),
+                   vec![names(&["C"]), names(&["A", "B", "D"]), names(&["E", "F"])]);
+
+        // A long chain shouldn't need a deep call stack.
+        let chain: DependencyGraph = (0..50_000)
+            .map(|i| (format!("step{}", i), vec![format!("step{}", i + 1)]))
+            .collect();
+        assert_eq!(strongly_connected_components(&chain).len(), 50_001);
+    }
+
+    #[test]
+    fn test_named_steps_and_durations() {
+        let steps_input = "Step fetch must be finished before step build can begin.
+Step configure must be finished before step build can begin.
+Step build must be finished before step test can begin.
+
+fetch 10
+configure 3
+build 20
+test 5
+package 2";
+        let (graph, details) = parse_dependency_graph(&mut steps_input.as_bytes()).expect("Parsing steps failed");
+        assert_result_ok(toposort(&graph), names(&["configure", "fetch", "build", "package", "test"]));
+        let policy = DurationPolicy::Table { durations: details.durations, fallback: None };
+        assert_result_ok(seconds_to_completion(&graph, &policy, 2), 35);
+        assert_result_ok(seconds_to_completion(&graph, &policy, 1), 40);
+
+        let missing = DurationPolicy::Table { durations: HashMap::new(), fallback: None };
+        assert!(seconds_to_completion(&graph, &missing, 1).is_err());
+        let alphabetical = DurationPolicy::Alphabetical { base_seconds: 60 };
+        assert!(seconds_to_completion(&graph, &alphabetical, 1).is_err());
+        assert_result_ok(alphabetical.duration("C"), 63);
+
+        let details = parse_step_details(&mut "A 100\n\nC 1\n".as_bytes()).expect("Parsing durations failed");
+        let mixed = DurationPolicy::Table { durations: details.durations, fallback: Some(Box::new(alphabetical)) };
+        assert_result_ok(mixed.duration("A"), 100);
+        assert_result_ok(mixed.duration("B"), 62);
+        assert!(parse_step_details(&mut "A 1\nA 2".as_bytes()).is_err());
+        assert!(parse_step_details(&mut "A one".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_schedule() {
+        let steps_input = "Step C must be finished before step A can begin.
+Step C must be finished before step F can begin.
+Step A must be finished before step B can begin.
+Step A must be finished before step D can begin.
+Step B must be finished before step E can begin.
+Step D must be finished before step E can begin.
+Step F must be finished before step E can begin.";
+        let (graph, _) = parse_dependency_graph(&mut steps_input.as_bytes()).expect("Parsing steps failed");
+        let durations = DurationPolicy::Alphabetical { base_seconds: 0 };
+        let workers = vec![Worker::new("ann"), Worker::new("bob")];
+        let schedule = schedule(&graph, &durations, &HashMap::new(), &workers).expect("Scheduling failed");
+        assert_eq!(schedule.makespan, 15);
+        assert_eq!(schedule.idle_time(0), 0);
+        assert_eq!(schedule.idle_time(1), 9);
+        assert_eq!(schedule.to_csv(), "worker,step,start,end
+ann,C,0,3
+ann,A,3,4
+bob,F,3,9
+ann,B,4,6
+ann,D,6,10
+ann,E,10,15
+");
+        assert_eq!(schedule.to_gantt(80), "    0             15
+ann |C==AB=D===E====| idle 0
+bob |...F=====......| idle 9
+");
+        assert_eq!(schedule.to_gantt(5), "    0   15
+ann |CBDE=| idle 0
+bob |.F=..| idle 9
+(each column is 3 seconds)
+");
+    }
+
+    #[test]
+    fn test_gantt_zero_duration_steps() {
+        let steps_input = "Step A must be finished before step B can begin.\nA 4\nB 0";
+        let (graph, details) = parse_dependency_graph(&mut steps_input.as_bytes()).expect("Parsing steps failed");
+        let durations = DurationPolicy::Table { durations: details.durations, fallback: None };
+        let delayed = schedule(&graph, &durations, &HashMap::new(), &[Worker::new("ann")]).expect("Scheduling failed");
+        assert_eq!(delayed.makespan, 4);
+        assert_eq!(delayed.to_gantt(80), "    0   4
+ann |A===| idle 0; zero-length: B at 4
+");
+
+        let (graph, details) = parse_dependency_graph(&mut "A 0\nB 0".as_bytes()).expect("Parsing steps failed");
+        let durations = DurationPolicy::Table { durations: details.durations, fallback: None };
+        let instant = schedule(&graph, &durations, &HashMap::new(), &[Worker::new("ann")]).expect("Scheduling failed");
+        assert_eq!(instant.makespan, 0);
+        assert_eq!(instant.to_gantt(80), "    0
+ann || idle 0; zero-length: A at 0, B at 0
+");
+    }
+
+    #[test]
+    fn test_heterogeneous_workers() {
+        let steps_input = "Step fetch must be finished before step build can begin.
+Step build must be finished before step test can begin.
+Step docs must be finished before step test can begin.
+fetch 4
+build 10 compiler
+docs 6
+test 2";
+        let (graph, details) = parse_dependency_graph(&mut steps_input.as_bytes()).expect("Parsing steps failed");
+        let durations = DurationPolicy::Table { durations: details.durations, fallback: None };
+        let workers = parse_workers(&mut "intern 0.5\nsenior 2 compiler\n".as_bytes()).expect("Parsing workers failed");
+        let schedule = schedule(&graph, &durations, &details.required_skills, &workers).expect("Scheduling failed");
+        assert_eq!(schedule.to_csv(), "worker,step,start,end
+senior,docs,0,3
+intern,fetch,0,8
+senior,build,8,13
+senior,test,13,14
+");
+        assert_eq!(schedule.idle_time(0), 6);
+        assert_eq!(schedule.idle_time(1), 5);
+
+        let unskilled = vec![Worker::new("intern")];
+        let error = super::schedule(&graph, &durations, &details.required_skills, &unskilled).expect_err("Missing skill not detected");
+        assert_eq!(error.to_string(), "No worker can do step build, which needs skill compiler");
+        assert!(parse_workers(&mut "slacker 0".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_critical_path() {
+        let steps = "Step C must be finished before step A can begin.
+Step C must be finished before step F can begin.
+Step A must be finished before step B can begin.
+Step A must be finished before step D can begin.
+Step B must be finished before step E can begin.
+Step D must be finished before step E can begin.
+Step F must be finished before step E can begin.";
+        let (graph, _) = parse_dependency_graph(&mut steps.as_bytes()).expect("Parsing steps failed");
+        let analysis = critical_path(&graph, &DurationPolicy::Alphabetical { base_seconds: 0 }).expect("Analysis failed");
+        assert_eq!(analysis.makespan, 14);
+        assert_eq!(analysis.critical_path, names(&["C", "F", "E"]));
+        let slack: Vec<(&str, u64)> = analysis.timings.iter().map(|(step, t)| (step.as_str(), t.slack)).collect();
+        assert_eq!(slack, vec![("A", 1), ("B", 3), ("C", 0), ("D", 1), ("E", 0), ("F", 0)]);
+        assert_eq!(analysis.timings["D"], StepTiming { duration: 4, earliest_start: 4, latest_start: 5, slack: 1 });
+        assert_eq!(analysis.makespan_lower_bound(2), 14);
+        assert_eq!(analysis.makespan_lower_bound(1), 21);
+        // The bound really is a bound.
+        assert!(seconds_to_completion(&graph, &DurationPolicy::Alphabetical { base_seconds: 0 }, 2).unwrap() >= 14);
+
+        let (cyclic, _) = parse_dependency_graph(&mut "Step A must be finished before step A can begin.".as_bytes())
+            .expect("Parsing steps failed");
+        assert!(critical_path(&cyclic, &DurationPolicy::Alphabetical { base_seconds: 0 }).is_err());
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let steps_input = "Step fetch must be finished before step build can begin.
+Step configure must be finished before step build can begin.
+Step build must be finished before step test can begin.
+fetch 10
+build 20 compiler
+package 2";
+        let (graph, details) = parse_dependency_graph(&mut steps_input.as_bytes()).expect("Parsing steps failed");
+        let json = to_json(&graph, &details, None).expect("Export failed");
+        assert_eq!(from_json_document(&json).expect("Import failed"), (graph.clone(), details));
+        let (graph, details) = from_json_document(&json).unwrap();
+        let puzzle_syntax = to_puzzle_syntax(&graph, &details);
+        assert_eq!(puzzle_syntax, "Step build must be finished before step test can begin.
+Step configure must be finished before step build can begin.
+Step fetch must be finished before step build can begin.
+build 20 compiler
+fetch 10
+package 2
+");
+        assert_eq!(parse_dependency_graph(&mut puzzle_syntax.as_bytes()).expect("Parsing steps failed"), (graph, details));
+
+        let hand_written = r#"{"steps": [{"name": "A", "dependents": ["B"], "duration": 4}, {"name": "B"}]}"#;
+        let (graph, details) = from_json_document(hand_written).expect("Import failed");
+        assert_result_ok(toposort(&graph), names(&["A", "B"]));
+        assert_eq!(details.durations["A"], 4);
+        assert!(from_json_document(r#"{"steps": [{"name": "A"}, {"name": "A"}]}"#).is_err());
+        assert!(from_json_document("not json").is_err());
+    }
+
+    #[test]
+    fn test_export_with_schedule() {
+        let steps_input = "Step C must be finished before step A can begin.
+Step C must be finished before step F can begin.";
+        let (graph, details) = parse_dependency_graph(&mut steps_input.as_bytes()).expect("Parsing steps failed");
+        let durations = DurationPolicy::Alphabetical { base_seconds: 0 };
+        let schedule = schedule(&graph, &durations, &HashMap::new(), &[Worker::new("ann")]).expect("Scheduling failed");
+        let analysis = critical_path(&graph, &durations).expect("Analysis failed");
+        assert_eq!(to_dot(&graph, Some(&analysis), Some(&schedule)), r#"digraph steps {
+    "A" [label="A\n1s, slack 5\nann 3-4"];
+    "C" [label="C\n3s, slack 0\nann 0-3", color=red, penwidth=2];
+    "F" [label="F\n6s, slack 0\nann 4-10", color=red, penwidth=2];
+    "C" -> "A";
+    "C" -> "F" [color=red, penwidth=2];
+}
+"#);
+        assert_eq!(to_dot(&graph, None, None), r#"digraph steps {
+    "A" [label="A"];
+    "C" [label="C"];
+    "F" [label="F"];
+    "C" -> "A";
+    "C" -> "F";
+}
+"#);
+        let json = to_json(&graph, &details, Some(&schedule)).expect("Export failed");
+        // Quotes and backslashes in names are escaped in labels too.
+        let mut odd_names = DependencyGraph::new();
+        odd_names.insert("say \"hi\\".to_string(), vec![]);
+        assert_eq!(to_dot(&odd_names, None, None), "digraph steps {\n    \"say \\\"hi\\\\\" [label=\"say \\\"hi\\\\\"];\n}\n");
+        assert!(json.contains(r#""makespan": 10"#));
+        assert!(json.contains(r#""worker": "ann""#));
+        // The schedule is ignored on import.
+        assert_eq!(from_json_document(&json).expect("Import failed").0, graph);
+    }
+
+    #[test]
+    fn test_dot_highlights_every_critical_step() {
+        // A and F tie for the critical path after C, and X -> W -> Z
+        // ties with Y -> Z. X -> Z leaves a gap, so it isn't critical.
+        let steps_input = "Step C must be finished before step A can begin.
+Step C must be finished before step F can begin.
+Step X must be finished before step W can begin.
+Step W must be finished before step Z can begin.
+Step X must be finished before step Z can begin.
+Step Y must be finished before step Z can begin.
+C 3
+A 6
+F 6
+X 1
+W 7
+Y 8
+Z 1";
+        let (graph, details) = parse_dependency_graph(&mut steps_input.as_bytes()).expect("Parsing steps failed");
+        let durations = DurationPolicy::Table { durations: details.durations, fallback: None };
+        let analysis = critical_path(&graph, &durations).expect("Analysis failed");
+        assert_eq!(to_dot(&graph, Some(&analysis), None), r#"digraph steps {
+    "A" [label="A\n6s, slack 0", color=red, penwidth=2];
+    "C" [label="C\n3s, slack 0", color=red, penwidth=2];
+    "F" [label="F\n6s, slack 0", color=red, penwidth=2];
+    "W" [label="W\n7s, slack 0", color=red, penwidth=2];
+    "X" [label="X\n1s, slack 0", color=red, penwidth=2];
+    "Y" [label="Y\n8s, slack 0", color=red, penwidth=2];
+    "Z" [label="Z\n1s, slack 0", color=red, penwidth=2];
+    "C" -> "A" [color=red, penwidth=2];
+    "C" -> "F" [color=red, penwidth=2];
+    "W" -> "Z" [color=red, penwidth=2];
+    "X" -> "W" [color=red, penwidth=2];
+    "X" -> "Z";
+    "Y" -> "Z" [color=red, penwidth=2];
+}
+"#);
     }
 }
@@ -1,6 +1,7 @@
 use std::io;
-use std::io::BufRead;
-use failure::{Error, format_err};
+use std::io::{BufRead, Write};
+use std::mem;
+use failure::{Error, bail, ensure, format_err};
 
 pub fn aoc8(part2: bool) -> Result<(), Error> {
     let tree = parse_tree(&mut io::stdin().lock())?;
@@ -12,62 +13,433 @@ pub fn aoc8(part2: bool) -> Result<(), Error> {
     Ok(())
 }
 
+/// Prints every node's metadata and value, in the given style.
+pub fn aoc8_pretty(style: &str) -> Result<(), Error> {
+    let tree = parse_tree(&mut io::stdin().lock())?;
+    let style = match style {
+        "indented" => PrettyStyle::Indented,
+        "box" => PrettyStyle::BoxDrawing,
+        _ => bail!("Unknown style {}", style),
+    };
+    print!("{}", TreeAnalysis::new(&tree).pretty_print(style));
+    Ok(())
+}
+
 #[derive(Debug, PartialEq)]
-struct Tree {
-    children: Vec<Tree>,
-    metadata: Vec<u64>,
+pub struct Tree {
+    pub children: Vec<Tree>,
+    pub metadata: Vec<u64>,
 }
 
+/// Headers can claim any number of entries, so don't trust them for
+/// more than this much up-front allocation.
+const MAX_PREALLOCATED: u64 = 1024;
+
 impl Tree {
     fn new(num_children: u64, num_metadata: u64) -> Self {
         Tree {
-            children: Vec::with_capacity(num_children as usize),
-            metadata: Vec::with_capacity(num_metadata as usize),
+            children: Vec::with_capacity(num_children.min(MAX_PREALLOCATED) as usize),
+            metadata: Vec::with_capacity(num_metadata.min(MAX_PREALLOCATED) as usize),
+        }
+    }
+}
+
+impl Drop for Tree {
+    /// The default drop recurses once per level, which overflows the
+    /// stack on deep trees, so we flatten the descendants out first.
+    fn drop(&mut self) {
+        let mut descendants = mem::take(&mut self.children);
+        while let Some(mut node) = descendants.pop() {
+            descendants.append(&mut node.children);
         }
     }
 }
 
-fn parse_tree(input: &mut BufRead) -> Result<Tree, Error> {
-    let mut contents = String::new();
-    input.read_to_string(&mut contents)?;
-    let fields: Vec<u64> = contents.trim().split(' ').map(|s| s.parse::<u64>()).collect::<Result<_, _>>()?;
-    let root = parse_node(&mut fields.into_iter())?;
-    Ok(root)
+/// Reads whitespace-separated numbers from a `BufRead` a buffer at a
+/// time, without holding the whole input in memory.
+struct Fields<R: BufRead> {
+    input: R,
+}
+
+impl<R: BufRead> Fields<R> {
+    /// Returns the next number, or None at the end of the input.
+    fn next_field(&mut self) -> Result<Option<u64>, Error> {
+        let mut field: Option<u64> = None;
+        loop {
+            let (used, finished) = {
+                let buffer = self.input.fill_buf()?;
+                if buffer.is_empty() {
+                    return Ok(field);
+                }
+                let mut used = 0;
+                let mut finished = false;
+                for &byte in buffer {
+                    if byte.is_ascii_digit() {
+                        field = field.unwrap_or(0).checked_mul(10)
+                            .and_then(|f| f.checked_add(u64::from(byte - b'0')));
+                        ensure!(field.is_some(), "field too large");
+                    } else if byte.is_ascii_whitespace() {
+                        if field.is_some() {
+                            finished = true;
+                            break;
+                        }
+                    } else {
+                        bail!("can't understand character {:?}", byte as char);
+                    }
+                    used += 1;
+                }
+                (used, finished)
+            };
+            self.input.consume(used);
+            if finished {
+                return Ok(field);
+            }
+        }
+    }
+
+    fn expect_field(&mut self) -> Result<u64, Error> {
+        self.next_field()?.ok_or_else(|| format_err!("ran out of fields too early"))
+    }
+}
+
+/// A node whose children we're still reading.
+struct PartialNode {
+    node: Tree,
+    children_left: u64,
+    num_metadata: u64,
 }
 
-fn parse_node(fields: &mut impl Iterator<Item = u64>) -> Result<Tree, Error> {
-    let num_children = fields.next().ok_or_else(|| format_err!("ran out of fields too early"))?;
-    let num_metadata = fields.next().ok_or_else(|| format_err!("ran out of fields too early"))?;
-    let mut node = Tree::new(num_children, num_metadata);
-    for _ in 0..num_children {
-        node.children.push(parse_node(fields)?);
+impl PartialNode {
+    fn read_header(fields: &mut Fields<impl BufRead>) -> Result<Self, Error> {
+        let num_children = fields.expect_field()?;
+        let num_metadata = fields.expect_field()?;
+        Ok(PartialNode {
+            node: Tree::new(num_children, num_metadata),
+            children_left: num_children,
+            num_metadata,
+        })
     }
-    for _ in 0..num_metadata {
-        let metadata = fields.next().ok_or_else(|| format_err!("ran out of fields too early"))?;
-        node.metadata.push(metadata);
+}
+
+/// Parses a tree using an explicit stack rather than recursion, so
+/// that arbitrarily deep trees can't overflow the call stack.
+pub fn parse_tree(input: &mut impl BufRead) -> Result<Tree, Error> {
+    let mut fields = Fields { input };
+    let mut stack = vec![PartialNode::read_header(&mut fields)?];
+    loop {
+        let top = stack.last_mut().unwrap();
+        if top.children_left > 0 {
+            top.children_left -= 1;
+            let child = PartialNode::read_header(&mut fields)?;
+            stack.push(child);
+            continue;
+        }
+        let PartialNode { mut node, num_metadata, .. } = stack.pop().unwrap();
+        for _ in 0..num_metadata {
+            node.metadata.push(fields.expect_field()?);
+        }
+        match stack.last_mut() {
+            Some(parent) => parent.node.children.push(node),
+            None => {
+                ensure!(fields.next_field()?.is_none(), "extra fields after the root node");
+                return Ok(node);
+            },
+        }
     }
-    Ok(node)
+}
+
+/// Writes a tree in the same flat format `parse_tree` reads, again
+/// without recursing.
+pub fn write_tree(tree: &Tree, output: &mut impl Write) -> Result<(), Error> {
+    // Nodes we've written the header for, with the index of the next
+    // child to write.
+    let mut stack: Vec<(&Tree, usize)> = vec![(tree, 0)];
+    write!(output, "{} {}", tree.children.len(), tree.metadata.len())?;
+    while let Some((node, next_child)) = stack.last_mut() {
+        match node.children.get(*next_child) {
+            Some(child) => {
+                *next_child += 1;
+                write!(output, " {} {}", child.children.len(), child.metadata.len())?;
+                stack.push((child, 0));
+            },
+            None => {
+                for metadata in &node.metadata {
+                    write!(output, " {}", metadata)?;
+                }
+                stack.pop();
+            },
+        }
+    }
+    writeln!(output)?;
+    Ok(())
 }
 
 fn sum_metadata_entries(tree: &Tree) -> u64 {
-    let children_sum: u64 = tree.children.iter().map(sum_metadata_entries).sum();
-    let my_sum: u64 = tree.metadata.iter().sum();
-    children_sum + my_sum
+    let mut sum = 0;
+    let mut to_visit = vec![tree];
+    while let Some(node) = to_visit.pop() {
+        sum += node.metadata.iter().sum::<u64>();
+        to_visit.extend(&node.children);
+    }
+    sum
 }
 
 fn tree_value(tree: &Tree) -> u64 {
-    if tree.children.is_empty() {
-        sum_metadata_entries(tree)
-    } else {
-        let mut sum = 0;
-        for metadata in tree.metadata.iter() {
-            let index = metadata.checked_sub(1);
-            if let Some(child) = index.and(tree.children.get(index.unwrap() as usize)) {
-                // We could be repeating work here, but who gives a bibble
-                sum += tree_value(child);
+    TreeAnalysis::new(tree).value(0)
+}
+
+/// Visits nodes in pre-order, along with their depth.
+pub struct PreOrder<'a> {
+    stack: Vec<(&'a Tree, usize)>,
+}
+
+impl<'a> Iterator for PreOrder<'a> {
+    type Item = (&'a Tree, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (node, depth) = self.stack.pop()?;
+        self.stack.extend(node.children.iter().rev().map(|child| (child, depth + 1)));
+        Some((node, depth))
+    }
+}
+
+/// Visits nodes in post-order, along with their depth.
+pub struct PostOrder<'a> {
+    /// Each node on the current path with the index of the next child
+    /// to descend into.
+    stack: Vec<(&'a Tree, usize)>,
+}
+
+impl<'a> Iterator for PostOrder<'a> {
+    type Item = (&'a Tree, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let depth = self.stack.len().checked_sub(1)?;
+            let (node, next_child) = self.stack.last_mut().unwrap();
+            match node.children.get(*next_child) {
+                Some(child) => {
+                    *next_child += 1;
+                    self.stack.push((child, 0));
+                },
+                None => {
+                    let node = *node;
+                    self.stack.pop();
+                    return Some((node, depth));
+                },
+            }
+        }
+    }
+}
+
+/// Callbacks for `Tree::walk`. Both default to doing nothing.
+pub trait TreeVisitor {
+    /// Called before any of the node's children are visited.
+    fn enter(&mut self, _node: &Tree, _depth: usize) {}
+    /// Called after all of the node's children have been visited.
+    fn leave(&mut self, _node: &Tree, _depth: usize) {}
+}
+
+impl Tree {
+    pub fn pre_order(&self) -> PreOrder<'_> {
+        PreOrder { stack: vec![(self, 0)] }
+    }
+
+    pub fn post_order(&self) -> PostOrder<'_> {
+        PostOrder { stack: vec![(self, 0)] }
+    }
+
+    /// Walks the tree depth-first without recursing, calling the
+    /// visitor on the way into and out of each node.
+    pub fn walk(&self, visitor: &mut impl TreeVisitor) {
+        visitor.enter(self, 0);
+        let mut stack: Vec<(&Tree, usize)> = vec![(self, 0)];
+        while let Some((node, next_child)) = stack.last_mut() {
+            match node.children.get(*next_child) {
+                Some(child) => {
+                    *next_child += 1;
+                    stack.push((child, 0));
+                    visitor.enter(child, stack.len() - 1);
+                },
+                None => {
+                    let node = *node;
+                    stack.pop();
+                    visitor.leave(node, stack.len());
+                },
             }
         }
-        sum
+    }
+}
+
+pub enum PrettyStyle {
+    /// Two spaces of indentation per level.
+    Indented,
+    /// Branches drawn with box-drawing characters, like `tree`.
+    BoxDrawing,
+}
+
+/// Everything we know how to compute about each node, worked out in a
+/// single pass so that nothing is computed twice. Nodes are referred to
+/// by their position in a pre-order traversal, so the root is 0 and a
+/// node's descendants immediately follow it.
+pub struct TreeAnalysis<'a> {
+    nodes: Vec<&'a Tree>,
+    parents: Vec<Option<usize>>,
+    depths: Vec<usize>,
+    subtree_sizes: Vec<usize>,
+    metadata_sums: Vec<u64>,
+    values: Vec<u64>,
+}
+
+impl<'a> TreeAnalysis<'a> {
+    pub fn new(tree: &'a Tree) -> Self {
+        let mut analysis = TreeAnalysis {
+            nodes: vec![],
+            parents: vec![],
+            depths: vec![],
+            subtree_sizes: vec![],
+            metadata_sums: vec![],
+            values: vec![],
+        };
+        analysis.add_node(tree, None, 0);
+        let mut stack: Vec<(usize, usize)> = vec![(0, 0)];
+        while let Some((index, next_child)) = stack.last_mut() {
+            let index = *index;
+            match analysis.nodes[index].children.get(*next_child) {
+                Some(child) => {
+                    *next_child += 1;
+                    let child_index = analysis.nodes.len();
+                    analysis.add_node(child, Some(index), analysis.depths[index] + 1);
+                    stack.push((child_index, 0));
+                },
+                None => {
+                    // All the descendants are done, so their subtree
+                    // sizes are known and we can find the children.
+                    let children: Vec<usize> = analysis.children(index).collect();
+                    let node = analysis.nodes[index];
+                    let own_sum: u64 = node.metadata.iter().sum();
+                    analysis.subtree_sizes[index] = 1 + children.iter().map(|&c| analysis.subtree_sizes[c]).sum::<usize>();
+                    analysis.metadata_sums[index] = own_sum + children.iter().map(|&c| analysis.metadata_sums[c]).sum::<u64>();
+                    analysis.values[index] = if children.is_empty() {
+                        own_sum
+                    } else {
+                        node.metadata.iter()
+                            .filter_map(|&m| children.get(m.checked_sub(1)? as usize))
+                            .map(|&c| analysis.values[c])
+                            .sum()
+                    };
+                    stack.pop();
+                },
+            }
+        }
+        analysis
+    }
+
+    fn add_node(&mut self, node: &'a Tree, parent: Option<usize>, depth: usize) {
+        self.nodes.push(node);
+        self.parents.push(parent);
+        self.depths.push(depth);
+        // Filled in once the node's subtree has been visited.
+        self.subtree_sizes.push(0);
+        self.metadata_sums.push(0);
+        self.values.push(0);
+    }
+
+    /// The number of nodes in the tree.
+    pub fn num_nodes(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn node(&self, index: usize) -> &'a Tree {
+        self.nodes[index]
+    }
+
+    /// The node's value as defined by part 2.
+    pub fn value(&self, index: usize) -> u64 {
+        self.values[index]
+    }
+
+    /// The root has depth 0.
+    pub fn depth(&self, index: usize) -> usize {
+        self.depths[index]
+    }
+
+    /// The number of nodes in the subtree rooted here, including this
+    /// one.
+    pub fn subtree_size(&self, index: usize) -> usize {
+        self.subtree_sizes[index]
+    }
+
+    /// The sum of all metadata in the subtree rooted here.
+    pub fn metadata_sum(&self, index: usize) -> u64 {
+        self.metadata_sums[index]
+    }
+
+    pub fn parent(&self, index: usize) -> Option<usize> {
+        self.parents[index]
+    }
+
+    /// The indices of the node's children.
+    pub fn children(&self, index: usize) -> impl Iterator<Item = usize> + '_ {
+        let mut next = index + 1;
+        (0..self.nodes[index].children.len()).map(move |_| {
+            let child = next;
+            next += self.subtree_sizes[child];
+            child
+        })
+    }
+
+    /// Which child to take at each level to get from the root to the
+    /// node.
+    pub fn path(&self, index: usize) -> Vec<usize> {
+        let mut path = vec![];
+        let mut current = index;
+        while let Some(parent) = self.parents[current] {
+            path.push(self.children(parent).position(|c| c == current).unwrap());
+            current = parent;
+        }
+        path.reverse();
+        path
+    }
+
+    /// Finds the node at the end of a path from the root.
+    pub fn find(&self, path: &[usize]) -> Option<usize> {
+        let mut current = 0;
+        for &step in path {
+            current = self.children(current).nth(step)?;
+        }
+        Some(current)
+    }
+
+    /// One line per node, in pre-order, showing its metadata and value.
+    pub fn pretty_print(&self, style: PrettyStyle) -> String {
+        let mut output = String::new();
+        // For box drawing, what to draw for each ancestor's level:
+        // a continuing branch if it has later siblings, or a gap.
+        let mut branches: Vec<&str> = vec![];
+        for index in 0..self.num_nodes() {
+            let depth = self.depths[index];
+            let label = format!("{:?} value {}", self.nodes[index].metadata, self.values[index]);
+            match style {
+                PrettyStyle::Indented => output += &format!("{}{}\n", "  ".repeat(depth), label),
+                PrettyStyle::BoxDrawing => {
+                    branches.truncate(depth.saturating_sub(1));
+                    let is_last = self.parents[index].is_some_and(|parent| {
+                        index + self.subtree_sizes[index] == parent + self.subtree_sizes[parent]
+                    });
+                    let connector = match (depth, is_last) {
+                        (0, _) => "",
+                        (_, true) => "└── ",
+                        (_, false) => "├── ",
+                    };
+                    output += &format!("{}{}{}\n", branches.concat(), connector, label);
+                    if depth > 0 {
+                        branches.push(if is_last { "    " } else { "│   " });
+                    }
+                },
+            }
+        }
+        output
     }
 }
 
@@ -75,6 +447,8 @@ fn tree_value(tree: &Tree) -> u64 {
 mod tests {
     use super::*;
     use std::fmt::Debug;
+    use std::io::BufReader;
+    use rand::Rng;
 
     /// This function allows us to assert that a Result is
     /// Ok(expected) without requiring PartialEq on the Error type.
@@ -118,4 +492,133 @@ mod tests {
         let tree = parse_tree(&mut input.as_bytes()).expect("Couldn't parse test tree");
         assert_eq!(tree_value(&tree), 66);
     }
+
+    fn random_tree(rng: &mut impl Rng, depth: u32) -> Tree {
+        let num_children = if depth == 0 { 0 } else { rng.gen_range(0, 4) };
+        let mut tree = Tree::new(num_children, 0);
+        for _ in 0..num_children {
+            tree.children.push(random_tree(rng, depth - 1));
+        }
+        tree.metadata = (0..rng.gen_range(0, 4)).map(|_| rng.gen_range(0, 1000)).collect();
+        tree
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..100 {
+            let tree = random_tree(&mut rng, 6);
+            let mut serialized = vec![];
+            write_tree(&tree, &mut serialized).expect("Couldn't write tree");
+            // Tiny buffers make sure fields split across reads are handled.
+            let mut reader = BufReader::with_capacity(3, serialized.as_slice());
+            assert_result_ok(parse_tree(&mut reader), tree);
+        }
+        let mut serialized = vec![];
+        write_tree(&parse_tree(&mut "2 3 0 3 10 11 12 1 1 0 1 99 2 1 1 2".as_bytes()).unwrap(), &mut serialized).unwrap();
+        assert_eq!(String::from_utf8(serialized).unwrap(), "2 3 0 3 10 11 12 1 1 0 1 99 2 1 1 2\n");
+    }
+
+    #[test]
+    fn test_deep_tree() {
+        let depth = 1_000_000;
+        let mut input = "1 1 ".repeat(depth);
+        input += "0 1 7";
+        input += &" 1".repeat(depth);
+        input += "\n";
+        let tree = parse_tree(&mut input.as_bytes()).expect("Couldn't parse deep tree");
+        assert_eq!(sum_metadata_entries(&tree), depth as u64 + 7);
+        let mut serialized = vec![];
+        write_tree(&tree, &mut serialized).expect("Couldn't write tree");
+        assert_eq!(String::from_utf8(serialized).unwrap(), input);
+    }
+
+    #[test]
+    fn test_malformed_input() {
+        assert!(parse_tree(&mut "1 1 0".as_bytes()).is_err());
+        assert!(parse_tree(&mut "0 1 5 6".as_bytes()).is_err());
+        assert!(parse_tree(&mut "0 1 x".as_bytes()).is_err());
+        assert!(parse_tree(&mut "0 1 99999999999999999999".as_bytes()).is_err());
+        assert_result_ok(parse_tree(&mut "\n 0  1\t5 \n".as_bytes()), Tree { children: vec![], metadata: vec![5] });
+    }
+
+    #[test]
+    fn test_tree_analysis() {
+        let input = "2 3 0 3 10 11 12 1 1 0 1 99 2 1 1 2";
+        let tree = parse_tree(&mut input.as_bytes()).expect("Couldn't parse test tree");
+        let analysis = TreeAnalysis::new(&tree);
+        assert_eq!(analysis.num_nodes(), 4);
+        assert_eq!((0..4).map(|i| analysis.value(i)).collect::<Vec<_>>(), vec![66, 33, 0, 99]);
+        assert_eq!((0..4).map(|i| analysis.depth(i)).collect::<Vec<_>>(), vec![0, 1, 1, 2]);
+        assert_eq!((0..4).map(|i| analysis.subtree_size(i)).collect::<Vec<_>>(), vec![4, 1, 2, 1]);
+        assert_eq!((0..4).map(|i| analysis.metadata_sum(i)).collect::<Vec<_>>(), vec![138, 33, 101, 99]);
+        assert_eq!(analysis.path(3), vec![1, 0]);
+        assert_eq!(analysis.path(0), Vec::<usize>::new());
+        assert_eq!(analysis.find(&[1, 0]), Some(3));
+        assert_eq!(analysis.find(&[0, 0]), None);
+        assert_eq!(analysis.node(3).metadata, vec![99]);
+        assert_eq!(analysis.parent(3), Some(2));
+        assert_eq!(analysis.children(0).collect::<Vec<_>>(), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_traversals() {
+        let input = "2 3 0 3 10 11 12 1 1 0 1 99 2 1 1 2";
+        let tree = parse_tree(&mut input.as_bytes()).expect("Couldn't parse test tree");
+        let pre_order: Vec<(u64, usize)> = tree.pre_order().map(|(node, depth)| (node.metadata[0], depth)).collect();
+        assert_eq!(pre_order, vec![(1, 0), (10, 1), (2, 1), (99, 2)]);
+        let post_order: Vec<(u64, usize)> = tree.post_order().map(|(node, depth)| (node.metadata[0], depth)).collect();
+        assert_eq!(post_order, vec![(10, 1), (99, 2), (2, 1), (1, 0)]);
+
+        struct Recorder(Vec<String>);
+        impl TreeVisitor for Recorder {
+            fn enter(&mut self, node: &Tree, depth: usize) {
+                self.0.push(format!("enter {} {}", node.metadata[0], depth));
+            }
+            fn leave(&mut self, node: &Tree, depth: usize) {
+                self.0.push(format!("leave {} {}", node.metadata[0], depth));
+            }
+        }
+        let mut recorder = Recorder(vec![]);
+        tree.walk(&mut recorder);
+        assert_eq!(recorder.0, vec!["enter 1 0", "enter 10 1", "leave 10 1", "enter 2 1", "enter 99 2",
+                                    "leave 99 2", "leave 2 1", "leave 1 0"]);
+    }
+
+    #[test]
+    fn test_pretty_print() {
+        let input = "2 3 0 3 10 11 12 1 1 0 1 99 2 1 1 2";
+        let tree = parse_tree(&mut input.as_bytes()).expect("Couldn't parse test tree");
+        let analysis = TreeAnalysis::new(&tree);
+        assert_eq!(analysis.pretty_print(PrettyStyle::Indented), "[1, 1, 2] value 66
+  [10, 11, 12] value 33
+  [2] value 0
+    [99] value 99
+");
+        assert_eq!(analysis.pretty_print(PrettyStyle::BoxDrawing), "[1, 1, 2] value 66
+├── [10, 11, 12] value 33
+└── [2] value 0
+    └── [99] value 99
+");
+        let input = "2 0 1 0 0 1 1 0 0";
+        let tree = parse_tree(&mut input.as_bytes()).expect("Couldn't parse test tree");
+        assert_eq!(TreeAnalysis::new(&tree).pretty_print(PrettyStyle::BoxDrawing), "[] value 0
+├── [] value 0
+│   └── [1] value 1
+└── [] value 0
+");
+    }
+
+    #[test]
+    fn test_deep_tree_analysis() {
+        let depth = 1_000_000;
+        let input = format!("{}0 1 7{}", "1 1 ".repeat(depth), " 1".repeat(depth));
+        let tree = parse_tree(&mut input.as_bytes()).expect("Couldn't parse deep tree");
+        let analysis = TreeAnalysis::new(&tree);
+        assert_eq!(analysis.value(0), 7);
+        assert_eq!(analysis.subtree_size(0), depth + 1);
+        assert_eq!(analysis.depth(depth), depth);
+        assert_eq!(tree.post_order().count(), depth + 1);
+        assert_eq!(tree.pre_order().map(|(_, d)| d).max(), Some(depth));
+    }
 }
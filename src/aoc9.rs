@@ -1,19 +1,25 @@
 use std::io;
 use std::io::Read;
-use std::cell::RefCell;
-use std::rc::{Weak, Rc};
+use std::fmt::{self, Display, Formatter};
 use regex::Regex;
-use failure::{Error, format_err};
-
-pub fn aoc9(part2: bool) -> Result<(), Error> {
-    let mut line = String::new();
-    io::stdin().lock().read_to_string(&mut line)?;
-    let (num_players, num_marbles) = parse_game_settings(&line)?;
-    let mut marbles = Marbles::new(num_players as usize);
+use failure::{Error, ensure, format_err};
+
+pub fn aoc9(part2: bool, show_log: bool) -> Result<(), Error> {
+    let mut input = String::new();
+    io::stdin().lock().read_to_string(&mut input)?;
+    let mut lines = input.lines();
+    let (num_players, last_marble) = parse_game_settings(lines.next().unwrap_or(""))?;
+    let rules = parse_rules(lines)?;
+    let mut marbles = Marbles::with_rules(num_players as usize, rules)?;
     if part2 {
-        marbles.play(num_marbles * 100);
+        marbles.play_until(last_marble * 100);
     } else {
-        marbles.play(num_marbles);
+        marbles.play_until(last_marble);
+    }
+    if show_log {
+        for event in &marbles.events {
+            println!("{}", event);
+        }
     }
     println!("Highest score: {}", marbles.highest_score());
     Ok(())
@@ -27,150 +33,325 @@ fn parse_game_settings(line: &str) -> Result<(u64, u64), Error> {
     Ok((num_players, num_marbles))
 }
 
-struct Node<T> {
-    next: Option<Rc<RefCell<Node<T>>>>,
-    prev: Option<Weak<RefCell<Node<T>>>>,
-    value: T,
+/// The rules of the marble game. The defaults are the puzzle's.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GameRules {
+    /// Marbles numbered with a multiple of this are kept by the player
+    /// instead of being placed, and score.
+    pub scoring_modulus: u64,
+    /// How many marbles counter-clockwise of the current marble the
+    /// marble taken on a scoring turn is.
+    pub removal_offset: usize,
+    /// New marbles are placed between the marbles this many and one
+    /// fewer places clockwise of the current marble.
+    pub insertion_offset: usize,
+    /// The circle before the first turn, clockwise from the current
+    /// marble. Numbering continues from the highest of these.
+    pub starting_marbles: Vec<u64>,
 }
 
-impl<T> Node<T> {
-    fn new_rc(value: T) -> Rc<RefCell<Self>> {
-        Rc::new(RefCell::new(Node {
-            next: None,
-            prev: None,
-            value,
-        }))
+impl Default for GameRules {
+    fn default() -> Self {
+        GameRules {
+            scoring_modulus: 23,
+            removal_offset: 7,
+            insertion_offset: 2,
+            starting_marbles: vec![0],
+        }
     }
+}
 
-    fn remove(&mut self) {
-        if let Some(ref mut prev) = self.prev {
-            prev.upgrade().unwrap().borrow_mut().next = self.next.clone();
+/// Parses `key: value` lines overriding the default rules, e.g.
+/// `scoring modulus: 11` or `starting marbles: 0 5 3`.
+fn parse_rules<'a>(lines: impl Iterator<Item = &'a str>) -> Result<GameRules, Error> {
+    let mut rules = GameRules::default();
+    for line in lines.map(str::trim).filter(|l| !l.is_empty()) {
+        let (key, value) = line.split_once(':').ok_or_else(|| format_err!("Can't understand rule {}", line))?;
+        let value = value.trim();
+        let bad_value = || format_err!("Can't understand value in rule {}", line);
+        match key.trim() {
+            "scoring modulus" => rules.scoring_modulus = value.parse().map_err(|_| bad_value())?,
+            "removal offset" => rules.removal_offset = value.parse().map_err(|_| bad_value())?,
+            "insertion offset" => rules.insertion_offset = value.parse().map_err(|_| bad_value())?,
+            "starting marbles" => {
+                rules.starting_marbles = value.split_whitespace().map(|m| m.parse()).collect::<Result<_, _>>()
+                    .map_err(|_| bad_value())?;
+            },
+            other => return Err(format_err!("Unknown rule {}", other)),
         }
-        if let Some(ref mut next) = self.next {
-            next.borrow_mut().prev = self.prev.clone();
+    }
+    Ok(rules)
+}
+
+/// A slot in the arena. Removed slots keep their links stale and are
+/// reused by later insertions.
+struct Slot<T> {
+    value: Option<T>,
+    next: usize,
+    prev: usize,
+}
+
+/// A circular doubly linked list whose nodes live in a single `Vec`
+/// and link to each other by index, with a cursor pointing at the
+/// current element. Moving the cursor and inserting or removing at it
+/// are all cheap, and nothing is allocated per element once the arena
+/// has grown.
+pub struct CircularList<T> {
+    slots: Vec<Slot<T>>,
+    /// Indices of removed slots, ready for reuse.
+    free: Vec<usize>,
+    /// The current element, or None if the list is empty.
+    cursor: Option<usize>,
+    len: usize,
+}
+
+impl<T> CircularList<T> {
+    pub fn new() -> Self {
+        Self::with_capacity(0)
+    }
+
+    /// Makes room for `capacity` elements up front.
+    pub fn with_capacity(capacity: usize) -> Self {
+        CircularList {
+            slots: Vec::with_capacity(capacity),
+            free: vec![],
+            cursor: None,
+            len: 0,
         }
     }
 
-    fn insert(node: &mut Rc<RefCell<Node<T>>>, value: T) {
-        let new_node = Node {
-            next: node.borrow().next.clone(),
-            prev: Some(Rc::downgrade(node)),
-            value,
-        };
-        let new_rc = Rc::new(RefCell::new(new_node));
-        let mut node_mut = node.borrow_mut();
-        if let Some(ref mut next) = node_mut.next {
-            if Rc::ptr_eq(next, node) {
-                // This is a single-element circularized list. Have to
-                // treat this specially to avoid mutably borrowing the
-                // same node twice.
-                node_mut.prev = Some(Rc::downgrade(&new_rc));
-            } else {
-                next.borrow_mut().prev = Some(Rc::downgrade(&new_rc));
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The element under the cursor.
+    pub fn current(&self) -> Option<&T> {
+        self.cursor.and_then(|i| self.slots[i].value.as_ref())
+    }
+
+    /// Moves the cursor `n` elements clockwise.
+    pub fn move_forward(&mut self, n: usize) {
+        if let Some(mut i) = self.cursor {
+            for _ in 0..n % self.len {
+                i = self.slots[i].next;
             }
+            self.cursor = Some(i);
         }
-        node_mut.next = Some(new_rc);
     }
 
-    /// Circularize a *single-node* list.
-    fn circularize(node: &mut Rc<RefCell<Node<T>>>) {
-        node.borrow_mut().next = Some(node.clone());
-        node.borrow_mut().prev = Some(Rc::downgrade(node));
+    /// Moves the cursor `n` elements counter-clockwise.
+    pub fn move_back(&mut self, n: usize) {
+        if let Some(mut i) = self.cursor {
+            for _ in 0..n % self.len {
+                i = self.slots[i].prev;
+            }
+            self.cursor = Some(i);
+        }
     }
 
-    fn iter(node: &mut Rc<RefCell<Node<T>>>) -> NodeIter<T> {
-        NodeIter {
-            cur_node: Some(node.clone()),
-            direction: Direction::Right,
+    /// Inserts a value just clockwise of the cursor, leaving the cursor
+    /// where it is. In an empty list the new value becomes current.
+    pub fn insert_after(&mut self, value: T) {
+        let (prev, next) = match self.cursor {
+            Some(i) => (i, self.slots[i].next),
+            None => {
+                // The new slot links to itself; patched up below once
+                // we know its index.
+                let index = self.free.last().cloned().unwrap_or(self.slots.len());
+                (index, index)
+            },
+        };
+        let slot = Slot { value: Some(value), next, prev };
+        let index = match self.free.pop() {
+            Some(index) => {
+                self.slots[index] = slot;
+                index
+            },
+            None => {
+                self.slots.push(slot);
+                self.slots.len() - 1
+            },
+        };
+        self.slots[prev].next = index;
+        self.slots[next].prev = index;
+        if self.cursor.is_none() {
+            self.cursor = Some(index);
         }
+        self.len += 1;
     }
 
-    fn reverse_iter(node: &mut Rc<RefCell<Node<T>>>) -> NodeIter<T> {
-        NodeIter {
-            cur_node: Some(node.clone()),
-            direction: Direction::Left,
+    /// Removes and returns the current element, moving the cursor to
+    /// the element that was clockwise of it.
+    pub fn remove_current(&mut self) -> Option<T> {
+        let index = self.cursor?;
+        let Slot { next, prev, .. } = self.slots[index];
+        self.slots[prev].next = next;
+        self.slots[next].prev = prev;
+        self.len -= 1;
+        self.cursor = if self.len == 0 { None } else { Some(next) };
+        self.free.push(index);
+        self.slots[index].value.take()
+    }
+
+    /// Goes once around the list clockwise, starting at the cursor.
+    pub fn iter(&self) -> CircularIter<'_, T> {
+        CircularIter {
+            list: self,
+            index: self.cursor.unwrap_or(0),
+            remaining: self.len,
         }
     }
 }
 
-enum Direction {
-    Right,
-    Left,
+impl<T> Default for CircularList<T> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-struct NodeIter<T> {
-    cur_node: Option<Rc<RefCell<Node<T>>>>,
-    direction: Direction,
+pub struct CircularIter<'a, T> {
+    list: &'a CircularList<T>,
+    index: usize,
+    remaining: usize,
 }
 
-impl<T> Iterator for NodeIter<T> {
-    type Item = Rc<RefCell<Node<T>>>;
+impl<'a, T> Iterator for CircularIter<'a, T> {
+    type Item = &'a T;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.cur_node.is_some() {
-            let node = self.cur_node.as_mut().unwrap().clone();
-            let ret = Some(node.clone());
-            self.cur_node = match &self.direction {
-                Direction::Right => node.borrow_mut().next.clone(),
-                Direction::Left => node.borrow_mut().prev.clone().and_then(|weak| weak.upgrade()),
-            };
-            ret
-        } else {
-            None
+        if self.remaining == 0 {
+            return None;
         }
+        self.remaining -= 1;
+        let slot = &self.list.slots[self.index];
+        self.index = slot.next;
+        slot.value.as_ref()
     }
 }
 
-struct Marbles {
+/// A turn on which a player kept their marble and scored.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScoreEvent {
+    /// Turns are numbered from 1.
+    pub turn: u64,
+    /// Players are numbered from 0.
+    pub player: usize,
+    /// The marble the player kept.
+    pub marble: u64,
+    /// The marble taken from the circle, if there was one to take.
+    pub removed_marble: Option<u64>,
+    pub points: u64,
+}
+
+impl Display for ScoreEvent {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "Turn {}: player {} scored {} (marble {}", self.turn, self.player + 1, self.points, self.marble)?;
+        if let Some(removed) = self.removed_marble {
+            write!(f, " + marble {}", removed)?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// A game in progress.
+pub struct Marbles {
     num_marbles: u64,
-    cur_node: Rc<RefCell<Node<u64>>>,
+    circle: CircularList<u64>,
     num_players: usize,
     cur_player: usize,
     scores: Vec<u64>,
+    rules: GameRules,
+    turn: u64,
+    /// Every scoring turn, in order.
+    pub events: Vec<ScoreEvent>,
 }
 
 impl Marbles {
-    fn new(num_players: usize) -> Self {
-        let mut node = Node::new_rc(0);
-        Node::circularize(&mut node);
-        Marbles {
-            cur_node: node,
-            num_marbles: 1,
+    pub fn new(num_players: usize) -> Self {
+        Self::with_rules(num_players, GameRules::default()).unwrap()
+    }
+
+    pub fn with_rules(num_players: usize, rules: GameRules) -> Result<Self, Error> {
+        ensure!(num_players > 0, "Need at least one player");
+        ensure!(rules.scoring_modulus > 0, "Scoring modulus must be positive");
+        ensure!(rules.insertion_offset > 0, "Insertion offset must be positive");
+        ensure!(!rules.starting_marbles.is_empty(), "Need at least one starting marble");
+        let mut circle = CircularList::new();
+        for &marble in &rules.starting_marbles {
+            circle.insert_after(marble);
+            circle.move_forward(1);
+        }
+        // The cursor ends up on the last marble, just counter-clockwise
+        // of the first.
+        circle.move_forward(1);
+        Ok(Marbles {
+            circle,
+            num_marbles: rules.starting_marbles.iter().max().unwrap() + 1,
             num_players,
             cur_player: 0,
             scores: vec![0; num_players],
-        }
+            rules,
+            turn: 0,
+            events: vec![],
+        })
     }
 
     fn place_next(&mut self) {
-        if self.num_marbles % 23 == 0 {
-            self.scores[self.cur_player] += self.num_marbles;
-            let mut marbles = Node::reverse_iter(&mut self.cur_node).skip(6);
-            let next_marble = marbles.next().unwrap();
-            let marble_to_remove = marbles.next().unwrap();
-            marble_to_remove.borrow_mut().remove();
-            self.scores[self.cur_player] += marble_to_remove.borrow().value;
-            self.cur_node = next_marble;
+        self.turn += 1;
+        if self.num_marbles % self.rules.scoring_modulus == 0 {
+            // Removing leaves the cursor on the marble clockwise of
+            // the removed one, which becomes the current marble.
+            self.circle.move_back(self.rules.removal_offset);
+            let removed_marble = self.circle.remove_current();
+            let points = self.num_marbles + removed_marble.unwrap_or(0);
+            self.scores[self.cur_player] += points;
+            self.events.push(ScoreEvent {
+                turn: self.turn,
+                player: self.cur_player,
+                marble: self.num_marbles,
+                removed_marble,
+                points,
+            });
         } else {
-            let mut iter = Node::iter(&mut self.cur_node);
-            let mut insert_node = iter.nth(1).unwrap();
-            Node::insert(&mut insert_node, self.num_marbles);
-            self.cur_node = Node::iter(&mut insert_node).nth(1).unwrap();
+            self.circle.move_forward(self.rules.insertion_offset - 1);
+            self.circle.insert_after(self.num_marbles);
+            self.circle.move_forward(1);
         }
         self.num_marbles += 1;
         self.cur_player = (self.cur_player + 1) % self.num_players;
     }
 
     /// Play for the given number of turns.
-    fn play(&mut self, num_turns: u64) {
+    pub fn play(&mut self, num_turns: u64) {
+        // Almost every turn adds a marble, so reserve room for them all.
+        self.circle.slots.reserve(num_turns as usize);
         for _ in 0..num_turns {
             self.place_next();
         }
     }
 
-    fn highest_score(&self) -> u64 {
+    /// Play until the given marble has been used.
+    pub fn play_until(&mut self, last_marble: u64) {
+        self.play((last_marble + 1).saturating_sub(self.num_marbles));
+    }
+
+    pub fn highest_score(&self) -> u64 {
         *self.scores.iter().max().unwrap()
     }
+
+    /// The player's total score after each of their scoring turns, as
+    /// (turn, score) pairs.
+    pub fn score_timeline(&self, player: usize) -> Vec<(u64, u64)> {
+        let mut score = 0;
+        self.events.iter().filter(|e| e.player == player).map(|e| {
+            score += e.points;
+            (e.turn, score)
+        }).collect()
+    }
 }
 
 #[cfg(test)]
@@ -189,51 +370,63 @@ mod tests {
 
     #[test]
     fn test_simple_linked_list() {
-        let mut node_1 = Node::new_rc("foo");
-        Node::insert(&mut node_1, "bar");
-        Node::insert(&mut node_1, "baz");
+        let mut list = CircularList::new();
+        list.insert_after("foo");
+        list.insert_after("bar");
+        list.insert_after("baz");
         // Check that the values are in the order that we expect
-        let node_iter = Node::iter(&mut node_1);
-        let values: Vec<_> = node_iter.map(|n| n.borrow().value).collect();
-        assert_eq!(values, vec!["foo", "baz", "bar"]);
+        assert_eq!(list.iter().cloned().collect::<Vec<_>>(), vec!["foo", "baz", "bar"]);
 
         // Now add a value after the second node
-        let mut node_2 = Node::iter(&mut node_1).skip(1).next().unwrap();
-        Node::insert(&mut node_2, "quux");
-
-        let values: Vec<_> = Node::iter(&mut node_1).map(|n| n.borrow().value).collect();
-        assert_eq!(values, vec!["foo", "baz", "quux", "bar"]);
-
-        // Try iterating backwards
-        let values: Vec<_> = Node::reverse_iter(&mut node_2).map(|n| n.borrow().value).collect();
-        assert_eq!(values, vec!["baz", "foo"]);
-
-        node_2.borrow_mut().remove();
-
-        let values: Vec<_> = Node::iter(&mut node_1).map(|n| n.borrow().value).collect();
-        assert_eq!(values, vec!["foo", "quux", "bar"]);
+        list.move_forward(1);
+        list.insert_after("quux");
+        assert_eq!(list.current(), Some(&"baz"));
+        list.move_back(1);
+        assert_eq!(list.iter().cloned().collect::<Vec<_>>(), vec!["foo", "baz", "quux", "bar"]);
+
+        // Try moving backwards, wrapping around
+        list.move_back(2);
+        assert_eq!(list.current(), Some(&"quux"));
+        list.move_back(9);
+        assert_eq!(list.current(), Some(&"baz"));
+
+        assert_eq!(list.remove_current(), Some("baz"));
+        assert_eq!(list.current(), Some(&"quux"));
+        list.move_back(1);
+        assert_eq!(list.iter().cloned().collect::<Vec<_>>(), vec!["foo", "quux", "bar"]);
+        assert_eq!(list.len(), 3);
     }
 
     #[test]
     fn test_circularized_list() {
-        let mut node_1 = Node::new_rc("foo");
-        Node::circularize(&mut node_1);
-        let node_iter = Node::iter(&mut node_1);
-        let values: Vec<_> = node_iter.take(5).map(|n| n.borrow().value).collect();
-        assert_eq!(values, vec!["foo", "foo", "foo", "foo", "foo"]);
+        let mut list = CircularList::new();
+        list.insert_after("foo");
+        list.move_forward(5);
+        assert_eq!(list.current(), Some(&"foo"));
+        assert_eq!(list.iter().cloned().collect::<Vec<_>>(), vec!["foo"]);
 
         // Insert a new value and verify it makes sense
-        Node::insert(&mut node_1, "bar");
-        let node_iter = Node::iter(&mut node_1);
-        let values: Vec<_> = node_iter.take(5).map(|n| n.borrow().value).collect();
-        assert_eq!(values, vec!["foo", "bar", "foo", "bar", "foo"]);
-
-        // Remove a node and verify it's still a proper loop
-        let node_2 = Node::iter(&mut node_1).skip(1).next().unwrap();
-        node_2.borrow_mut().remove();
-        let node_iter = Node::iter(&mut node_1);
-        let values: Vec<_> = node_iter.take(5).map(|n| n.borrow().value).collect();
-        assert_eq!(values, vec!["foo", "foo", "foo", "foo", "foo"]);
+        list.insert_after("bar");
+        list.move_forward(3);
+        assert_eq!(list.current(), Some(&"bar"));
+        assert_eq!(list.iter().cloned().collect::<Vec<_>>(), vec!["bar", "foo"]);
+
+        // Remove nodes and verify it's still a proper loop
+        assert_eq!(list.remove_current(), Some("bar"));
+        list.move_back(3);
+        assert_eq!(list.iter().cloned().collect::<Vec<_>>(), vec!["foo"]);
+        assert_eq!(list.remove_current(), Some("foo"));
+        assert!(list.is_empty());
+        assert_eq!(list.current(), None);
+        assert_eq!(list.remove_current(), None);
+        assert_eq!(list.iter().count(), 0);
+        list.move_forward(1);
+
+        // Removed slots get reused.
+        list.insert_after("baz");
+        list.insert_after("quux");
+        assert_eq!(list.slots.len(), 2);
+        assert_eq!(list.iter().cloned().collect::<Vec<_>>(), vec!["baz", "quux"]);
     }
 
     #[test]
@@ -268,4 +461,59 @@ mod tests {
         let line = "438 players; last marble is worth 71626 points\n";
         assert_result_ok(parse_game_settings(line), (438, 71626));
     }
+
+    #[test]
+    fn test_score_events() {
+        let mut marbles = Marbles::new(9);
+        marbles.play_until(25);
+        assert_eq!(marbles.events, vec![ScoreEvent {
+            turn: 23,
+            player: 4,
+            marble: 23,
+            removed_marble: Some(9),
+            points: 32,
+        }]);
+        assert_eq!(marbles.events[0].to_string(), "Turn 23: player 5 scored 32 (marble 23 + marble 9)");
+        assert_eq!(marbles.score_timeline(4), vec![(23, 32)]);
+        assert_eq!(marbles.score_timeline(0), vec![]);
+
+        let mut marbles = Marbles::new(10);
+        marbles.play(1618);
+        let best = (0..10).max_by_key(|&p| marbles.scores[p]).unwrap();
+        let timeline = marbles.score_timeline(best);
+        assert_eq!(timeline.last().unwrap().1, 8317);
+        assert!(timeline.windows(2).all(|w| w[0].0 < w[1].0 && w[0].1 < w[1].1));
+        assert_eq!(marbles.events.iter().map(|e| e.points).sum::<u64>(), marbles.scores.iter().sum::<u64>());
+    }
+
+    #[test]
+    fn test_game_rules() {
+        let rules = parse_rules("scoring modulus: 5\nremoval offset: 2\n\ninsertion offset: 1\nstarting marbles: 10 20".lines())
+            .expect("Couldn't parse rules");
+        assert_eq!(rules, GameRules {
+            scoring_modulus: 5,
+            removal_offset: 2,
+            insertion_offset: 1,
+            starting_marbles: vec![10, 20],
+        });
+        let mut marbles = Marbles::with_rules(2, rules).expect("Invalid rules");
+        // Marbles go in right after the current one: 10 21 20, 10 21 22 20,
+        // 10 21 22 23 20, 10 21 22 23 24 20, then 25 scores and takes the
+        // marble two counter-clockwise of 24, which is 22.
+        marbles.play_until(25);
+        assert_eq!(marbles.circle.iter().cloned().collect::<Vec<_>>(), vec![23, 24, 20, 10, 21]);
+        assert_eq!(marbles.events, vec![ScoreEvent {
+            turn: 5,
+            player: 0,
+            marble: 25,
+            removed_marble: Some(22),
+            points: 47,
+        }]);
+
+        assert!(parse_rules("scoring modulus: x".lines()).is_err());
+        assert!(parse_rules("gravity: 9.8".lines()).is_err());
+        assert!(Marbles::with_rules(2, GameRules { insertion_offset: 0, ..GameRules::default() }).is_err());
+        assert!(Marbles::with_rules(2, GameRules { starting_marbles: vec![], ..GameRules::default() }).is_err());
+        assert_eq!(parse_rules("".lines()).unwrap(), GameRules::default());
+    }
 }
@@ -34,23 +34,79 @@ fn main() -> Result<(), Error> {
         .subcommand(SubCommand::with_name("aoc2")
                     .arg(Arg::with_name("part2")))
         .subcommand(SubCommand::with_name("aoc3")
-                    .arg(Arg::with_name("part2")))
+                    .arg(Arg::with_name("part2"))
+                    .arg(Arg::with_name("at-least")
+                         .long("at-least")
+                         .takes_value(true)
+                         .value_name("k")))
         .subcommand(SubCommand::with_name("aoc4")
-                    .arg(Arg::with_name("part2")))
+                    .arg(Arg::with_name("part2"))
+                    .arg(Arg::with_name("report")
+                         .long("report")
+                         .takes_value(true)
+                         .possible_values(&["table", "csv"])))
         .subcommand(SubCommand::with_name("aoc5")
-                    .arg(Arg::with_name("part2")))
+                    .arg(Arg::with_name("part2"))
+                    .arg(Arg::with_name("rules")
+                         .long("rules")
+                         .takes_value(true)))
         .subcommand(SubCommand::with_name("aoc6")
                     .arg(Arg::with_name("part2")))
         .subcommand(SubCommand::with_name("aoc7")
-                    .arg(Arg::with_name("part2")))
+                    .arg(Arg::with_name("part2"))
+                    .arg(Arg::with_name("components")
+                         .long("components"))
+                    .arg(Arg::with_name("critical-path")
+                         .long("critical-path"))
+                    .arg(Arg::with_name("durations")
+                         .long("durations")
+                         .takes_value(true))
+                    .arg(Arg::with_name("schedule")
+                         .long("schedule")
+                         .takes_value(true)
+                         .possible_values(&["gantt", "csv"]))
+                    .arg(Arg::with_name("workers")
+                         .long("workers")
+                         .takes_value(true))
+                    .arg(Arg::with_name("export")
+                         .long("export")
+                         .takes_value(true)
+                         .possible_values(&["dot", "json", "steps"]))
+                    .arg(Arg::with_name("from-json")
+                         .long("from-json")
+                         .requires("export"))
+                    .arg(Arg::with_name("with-schedule")
+                         .long("with-schedule")
+                         .requires("export")))
         .subcommand(SubCommand::with_name("aoc8")
-                    .arg(Arg::with_name("part2")))
+                    .arg(Arg::with_name("part2"))
+                    .arg(Arg::with_name("pretty")
+                         .long("pretty")
+                         .takes_value(true)
+                         .possible_values(&["indented", "box"])))
         .subcommand(SubCommand::with_name("aoc9")
-                    .arg(Arg::with_name("part2")))
+                    .arg(Arg::with_name("part2"))
+                    .arg(Arg::with_name("log")
+                         .long("log")))
         .subcommand(SubCommand::with_name("aoc10")
-                    .arg(Arg::with_name("part2")))
+                    .arg(Arg::with_name("part2"))
+                    .arg(Arg::with_name("region")
+                         .long("region")
+                         .takes_value(true)
+                         .allow_hyphen_values(true))
+                    .arg(Arg::with_name("steps")
+                         .long("steps")
+                         .takes_value(true)
+                         .allow_hyphen_values(true)
+                         .default_value("0..1000")))
         .subcommand(SubCommand::with_name("aoc11")
-                    .arg(Arg::with_name("part2")))
+                    .arg(Arg::with_name("part2"))
+                    .arg(Arg::with_name("rectangle")
+                         .long("rectangle"))
+                    .arg(Arg::with_name("grid")
+                         .long("grid"))
+                    .arg(Arg::with_name("zero-based")
+                         .long("zero-based")))
         .subcommand(SubCommand::with_name("aoc12")
                     .arg(Arg::with_name("part2")))
         .subcommand(SubCommand::with_name("aoc13")
@@ -83,15 +139,41 @@ fn main() -> Result<(), Error> {
     match matches.subcommand() {
         ("aoc1", Some(sub_matches)) => aoc1::aoc1(sub_matches.is_present("part2"))?,
         ("aoc2", Some(sub_matches)) => aoc2::aoc2(sub_matches.is_present("part2"))?,
-        ("aoc3", Some(sub_matches)) => aoc3::aoc3(sub_matches.is_present("part2"))?,
-        ("aoc4", Some(sub_matches)) => aoc4::aoc4(sub_matches.is_present("part2"))?,
-        ("aoc5", Some(sub_matches)) => aoc5::aoc5(sub_matches.is_present("part2"))?,
+        ("aoc3", Some(sub_matches)) => match sub_matches.value_of("at-least") {
+            Some(k) => aoc3::aoc3_at_least(k)?,
+            None => aoc3::aoc3(sub_matches.is_present("part2"))?,
+        },
+        ("aoc4", Some(sub_matches)) => match sub_matches.value_of("report") {
+            Some(format) => aoc4::aoc4_report(format)?,
+            None => aoc4::aoc4(sub_matches.is_present("part2"))?,
+        },
+        ("aoc5", Some(sub_matches)) => aoc5::aoc5(sub_matches.is_present("part2"), sub_matches.value_of("rules"))?,
         ("aoc6", Some(sub_matches)) => aoc6::aoc6(sub_matches.is_present("part2"))?,
-        ("aoc7", Some(sub_matches)) => aoc7::aoc7(sub_matches.is_present("part2"))?,
-        ("aoc8", Some(sub_matches)) => aoc8::aoc8(sub_matches.is_present("part2"))?,
-        ("aoc9", Some(sub_matches)) => aoc9::aoc9(sub_matches.is_present("part2"))?,
-        ("aoc10", Some(sub_matches)) => aoc10::aoc10(sub_matches.is_present("part2"))?,
-        ("aoc11", Some(sub_matches)) => aoc11::aoc11(sub_matches.is_present("part2"))?,
+        ("aoc7", Some(sub_matches)) => if sub_matches.is_present("components") {
+            aoc7::aoc7_components()?
+        } else if sub_matches.is_present("critical-path") {
+            aoc7::aoc7_critical_path(sub_matches.value_of("durations"))?
+        } else if let Some(format) = sub_matches.value_of("export") {
+            aoc7::aoc7_export(format, sub_matches.is_present("from-json"), sub_matches.is_present("with-schedule"),
+                              sub_matches.value_of("workers"), sub_matches.value_of("durations"))?
+        } else if let Some(format) = sub_matches.value_of("schedule") {
+            aoc7::aoc7_schedule(format, sub_matches.value_of("workers"), sub_matches.value_of("durations"))?
+        } else {
+            aoc7::aoc7(sub_matches.is_present("part2"), sub_matches.value_of("durations"))?
+        },
+        ("aoc8", Some(sub_matches)) => match sub_matches.value_of("pretty") {
+            Some(style) => aoc8::aoc8_pretty(style)?,
+            None => aoc8::aoc8(sub_matches.is_present("part2"))?,
+        },
+        ("aoc9", Some(sub_matches)) => aoc9::aoc9(sub_matches.is_present("part2"), sub_matches.is_present("log"))?,
+        ("aoc10", Some(sub_matches)) => match sub_matches.value_of("region") {
+            Some(region) => aoc10::aoc10_region(region, sub_matches.value_of("steps").unwrap())?,
+            None => aoc10::aoc10(sub_matches.is_present("part2"))?,
+        },
+        ("aoc11", Some(sub_matches)) => {
+            let indexing = if sub_matches.is_present("zero-based") { aoc11::Indexing::ZeroBased } else { aoc11::Indexing::OneBased };
+            aoc11::aoc11(sub_matches.is_present("part2"), sub_matches.is_present("rectangle"), sub_matches.is_present("grid"), indexing)?
+        },
         ("aoc12", Some(sub_matches)) => aoc12::aoc12(sub_matches.is_present("part2"))?,
         ("aoc13", Some(sub_matches)) => aoc13::aoc13(sub_matches.is_present("part2"))?,
         ("aoc14", Some(sub_matches)) => aoc14::aoc14(sub_matches.is_present("part2"))?,